@@ -0,0 +1,152 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+use reqwest::Client;
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+/// Summary of one successful write tool call.
+#[derive(Debug, Clone, Serialize)]
+pub struct AuditEvent {
+  pub tool: String,
+  pub project: Option<String>,
+  pub mr_iid: Option<u64>,
+  pub result_id: Option<Value>,
+  /// Seconds since the Unix epoch
+  pub timestamp: u64,
+}
+
+impl AuditEvent {
+  /// `project` and `mr_iid` are what the call's arguments resolved to, so a
+  /// merge request URL or a namespace and name are recorded as a path.
+  pub fn new(tool: &str, project: Option<String>, mr_iid: Option<u64>, result: &Value) -> Self {
+    Self {
+      tool: tool.to_string(),
+      project,
+      mr_iid,
+      result_id: ["id", "iid", "tag_name"]
+        .iter()
+        .find_map(|key| result.get(key))
+        .cloned(),
+      timestamp: SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default(),
+    }
+  }
+}
+
+/// Posts an `AuditEvent` to `audit.webhook_url` after every successful write.
+#[derive(Clone)]
+pub struct AuditWebhook {
+  http: Client,
+  url: String,
+}
+
+impl AuditWebhook {
+  /// `user_agent` is the one GitLab requests are sent with.
+  pub fn new(url: String, user_agent: &str) -> anyhow::Result<Self> {
+    let http = Client::builder()
+      .user_agent(user_agent)
+      .timeout(std::time::Duration::from_secs(10))
+      .build()?;
+    Ok(Self { http, url })
+  }
+
+  /// Delivers the event in the background. A failed delivery is logged and
+  /// never affects the tool call.
+  pub fn send(&self, event: AuditEvent) {
+    let http = self.http.clone();
+    let url = self.url.clone();
+    tokio::spawn(async move {
+      match http.post(&url).json(&event).send().await {
+        Ok(response) if response.status().is_success() => {}
+        Ok(response) => {
+          tracing::warn!(tool = %event.tool, status = response.status().as_u16(), "Audit webhook rejected event");
+        }
+        Err(err) => {
+          tracing::warn!(tool = %event.tool, error = %err, "Audit webhook delivery failed");
+        }
+      }
+    });
+  }
+}
+
+/// Stable FNV-1a hash of a tool call's arguments, so audit entries can be
+/// compared without storing comment bodies or file contents.
+pub fn payload_hash(arguments: Option<&Map<String, Value>>) -> String {
+  let payload = arguments
+    .map(|arguments| Value::Object(arguments.clone()).to_string())
+    .unwrap_or_default();
+  let hash = payload.bytes().fold(0xcbf29ce484222325u64, |hash, byte| {
+    (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3)
+  });
+  format!("{:016x}", hash)
+}
+
+#[cfg(feature = "database")]
+pub async fn init_table(pool: &sqlx::SqlitePool) -> sqlx::Result<()> {
+  sqlx::query(
+    "CREATE TABLE IF NOT EXISTS audit_log (
+      id INTEGER PRIMARY KEY AUTOINCREMENT,
+      tool TEXT NOT NULL,
+      project TEXT,
+      mr_iid INTEGER,
+      payload_hash TEXT NOT NULL,
+      status TEXT NOT NULL,
+      timestamp INTEGER NOT NULL
+    )",
+  )
+  .execute(pool)
+  .await?;
+  Ok(())
+}
+
+#[cfg(feature = "database")]
+pub async fn record(
+  pool: &sqlx::SqlitePool,
+  event: &AuditEvent,
+  payload_hash: &str,
+  status: &str,
+) -> sqlx::Result<()> {
+  sqlx::query(
+    "INSERT INTO audit_log (tool, project, mr_iid, payload_hash, status, timestamp)
+     VALUES (?, ?, ?, ?, ?, ?)",
+  )
+  .bind(&event.tool)
+  .bind(&event.project)
+  .bind(event.mr_iid.map(|iid| iid as i64))
+  .bind(payload_hash)
+  .bind(status)
+  .bind(event.timestamp as i64)
+  .execute(pool)
+  .await?;
+  Ok(())
+}
+
+/// The newest `limit` audit entries, newest first.
+#[cfg(feature = "database")]
+pub async fn recent(pool: &sqlx::SqlitePool, limit: u32) -> sqlx::Result<Vec<Value>> {
+  use sqlx::Row;
+
+  let rows = sqlx::query(
+    "SELECT id, tool, project, mr_iid, payload_hash, status, timestamp
+     FROM audit_log ORDER BY id DESC LIMIT ?",
+  )
+  .bind(i64::from(limit))
+  .fetch_all(pool)
+  .await?;
+
+  rows
+    .iter()
+    .map(|row| {
+      Ok(serde_json::json!({
+        "id": row.try_get::<i64, _>("id")?,
+        "tool": row.try_get::<String, _>("tool")?,
+        "project": row.try_get::<Option<String>, _>("project")?,
+        "mr_iid": row.try_get::<Option<i64>, _>("mr_iid")?,
+        "payload_hash": row.try_get::<String, _>("payload_hash")?,
+        "status": row.try_get::<String, _>("status")?,
+        "timestamp": row.try_get::<i64, _>("timestamp")?,
+      }))
+    })
+    .collect()
+}
@@ -0,0 +1,44 @@
+use std::path::PathBuf;
+use clap::{Parser, ValueEnum};
+
+/// GitLab merge request review tools over the Model Context Protocol.
+#[derive(Debug, Default, Parser)]
+#[command(
+  name = env!("CARGO_PKG_NAME"),
+  version,
+  about,
+  after_help = "Settings are resolved in this order: command-line flags, then the config file \
+(--config, a TOML document in GITLAB_MCP_CONFIG, ./config.toml, $XDG_CONFIG_HOME/gitlab-mcp/config.toml, ~/.config/gitlab-mcp/config.toml, \
+/config.toml), then environment variables (PORT, MCP_TELEMETRY_LEVEL, ...) when no config file is found. \
+RUST_LOG, when set, overrides the log level. GITLAB_URL and GITLAB_TOKEN are always read from the environment. \
+--insecure (or GITLAB_INSECURE=1) disables TLS certificate checks and is meant only for local development."
+)]
+pub struct Cli {
+  /// Config file to load instead of searching the default locations; "-" reads TOML from stdin
+  #[arg(long, value_name = "PATH")]
+  pub config: Option<PathBuf>,
+
+  /// Serve over HTTP streaming on this port
+  #[arg(long, value_name = "PORT")]
+  pub http_port: Option<u16>,
+
+  /// Transport to serve; http-streaming needs a port from --http-port, the config file, or PORT
+  #[arg(long, value_enum)]
+  pub transport: Option<CliTransport>,
+
+  /// Log level or filter directive, e.g. "debug" or "gitlab_mcp=trace"
+  #[arg(long, value_name = "LEVEL")]
+  pub log_level: Option<String>,
+
+  /// Accept invalid or self-signed TLS certificates from GitLab; local development only.
+  /// Also enabled by GITLAB_INSECURE=1
+  #[arg(long)]
+  pub insecure: bool,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum CliTransport {
+  Stdio,
+  #[value(alias = "http")]
+  HttpStreaming,
+}
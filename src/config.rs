@@ -1,45 +1,388 @@
-use serde::Deserialize;
-use config::{Config as ConfigBuilder, ConfigError, File};
-use std::path::Path;
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer};
+use config::{Config as ConfigBuilder, ConfigError, File, FileFormat};
+use crate::cli::{Cli, CliTransport};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct Config {
   pub server: ServerConfig,
   pub telemetry: TelemetryConfig,
+  #[serde(default)]
+  pub audit: AuditConfig,
+  #[serde(default)]
+  pub gitlab: GitLabConfig,
+  #[serde(default)]
+  pub review: ReviewConfig,
   #[cfg(feature = "auth")]
   pub redis: Option<RedisConfig>,
   #[cfg(feature = "database")]
   pub database: Option<DatabaseConfig>,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, Clone, Default)]
 pub struct ServerConfig {
   pub name: String,
   pub transport: TransportType,
+  /// Seconds to wait for in-flight tool calls to finish after a shutdown signal
+  #[serde(default)]
+  pub shutdown_grace_secs: Option<u64>,
+  /// Skip every GitLab write and return the request that would have been sent
+  #[serde(default)]
+  pub dry_run: bool,
+  /// Do not register any tool that modifies GitLab
+  #[serde(default)]
+  pub read_only: bool,
+  /// Project paths or IDs the server may access; `*` globs are allowed and
+  /// an empty list allows every project
+  #[serde(default)]
+  pub allowed_projects: Vec<String>,
+  /// Project paths or IDs the server must never access, checked before
+  /// `allowed_projects`
+  #[serde(default)]
+  pub denied_projects: Vec<String>,
+  /// Names of the tools to register; every tool is registered when unset
+  #[serde(default)]
+  pub enabled_tools: Option<Vec<String>>,
+  /// Attach debugging metadata, such as the number of GitLab requests a
+  /// call made, to every tool result's `_meta`
+  #[serde(default)]
+  pub debug: bool,
+  /// Cap on GitLab requests per second; unlimited when unset or 0
+  #[serde(default)]
+  pub requests_per_second: Option<u32>,
+  /// Branch to use when a tool omits `ref` and the project reports no
+  /// default branch (e.g. an empty repository)
+  #[serde(default)]
+  pub fallback_branch: Option<String>,
+  /// Register the `gitlab_rest` tool, which sends arbitrary requests to the
+  /// GitLab REST API
+  #[serde(default)]
+  pub enable_rest_passthrough: bool,
+  /// Overall deadline, in seconds, for a single tool call however many
+  /// GitLab requests it makes; unlimited when unset
+  #[serde(default)]
+  pub tool_timeout_secs: Option<u64>,
+  /// Look up the token's scopes and expiry at startup and warn when it
+  /// cannot write or expires soon. Needs a personal, project, or group
+  /// access token; other tokens cannot read themselves.
+  #[serde(default)]
+  pub check_token_scopes: bool,
+  /// Instructions sent to clients on `initialize` in place of the built-in
+  /// review workflow guidance
+  #[serde(default)]
+  pub instructions: Option<String>,
+  /// Also return JSON tool results as structured content, next to the
+  /// pretty-printed text, for clients that read `structuredContent`
+  #[serde(default)]
+  pub structured_output: bool,
+  /// Seconds a merge request's cached changes and diff_refs stay fresh
+  /// (default 60)
+  #[serde(default)]
+  pub merge_request_cache_ttl_secs: Option<u64>,
+  /// Merge requests kept in that cache (default 32); 0 disables it
+  #[serde(default)]
+  pub merge_request_cache_size: Option<usize>,
+  /// Largest tool result, in bytes of text, sent to a client; unlimited
+  /// when unset
+  #[serde(default)]
+  pub max_response_bytes: Option<usize>,
+  /// What to do with a result over `max_response_bytes`
+  #[serde(default)]
+  pub oversize_response: OversizeResponse,
 }
 
-#[derive(Debug, Deserialize, Clone)]
-#[serde(rename_all = "lowercase")]
+/// How a tool result larger than `max_response_bytes` is handled.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OversizeResponse {
+  /// Cut the text at the limit and append a truncation marker
+  #[default]
+  Truncate,
+  /// Fail the call with an error that asks for a narrower request
+  Error,
+}
+
+impl FromStr for OversizeResponse {
+  type Err = String;
+
+  fn from_str(value: &str) -> Result<Self, Self::Err> {
+    match normalize_name(value).as_str() {
+      "truncate" => Ok(OversizeResponse::Truncate),
+      "error" => Ok(OversizeResponse::Error),
+      _ => Err(format!(
+        "unknown oversize response '{}'; expected \"truncate\" or \"error\"",
+        value
+      )),
+    }
+  }
+}
+
+impl<'de> Deserialize<'de> for OversizeResponse {
+  fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+    String::deserialize(deserializer)?.parse().map_err(D::Error::custom)
+  }
+}
+
+impl ServerConfig {
+  pub fn shutdown_grace(&self) -> std::time::Duration {
+    std::time::Duration::from_secs(self.shutdown_grace_secs.unwrap_or(10))
+  }
+
+  /// The deadline for one tool call. A client's `_meta.timeout_secs` can
+  /// only shorten `tool_timeout_secs`; a requested 0 means the default.
+  pub fn tool_timeout(&self, requested_secs: Option<u64>) -> Option<std::time::Duration> {
+    let limit = self.tool_timeout_secs.filter(|secs| *secs > 0);
+    let secs = match (requested_secs.filter(|secs| *secs > 0), limit) {
+      (Some(requested), Some(limit)) => Some(requested.min(limit)),
+      (requested, limit) => requested.or(limit),
+    };
+    secs.map(std::time::Duration::from_secs)
+  }
+}
+
+#[derive(Debug, Clone, Default)]
 pub enum TransportType {
+  #[default]
   Stdio,
-  #[serde(rename = "http-streaming")]
   HttpStreaming { port: u16 },
 }
 
-#[derive(Debug, Deserialize, Clone)]
+impl TransportType {
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      TransportType::Stdio => "stdio",
+      TransportType::HttpStreaming { .. } => "http-streaming",
+    }
+  }
+}
+
+/// Lowercases a config value and treats `_` and `-` alike, so `HTTP_Streaming`
+/// and `http-streaming` compare equal.
+fn normalize_name(value: &str) -> String {
+  value.trim().to_lowercase().replace('_', "-")
+}
+
+/// Whether an environment flag such as `GITLAB_INSECURE` is switched on.
+fn is_truthy(value: &str) -> bool {
+  matches!(normalize_name(value).as_str(), "1" | "true" | "yes" | "on")
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawTransport {
+  Name(String),
+  WithOptions(std::collections::HashMap<String, HttpTransportOptions>),
+}
+
+#[derive(Deserialize)]
+struct HttpTransportOptions {
+  port: u16,
+}
+
+impl<'de> Deserialize<'de> for TransportType {
+  fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+    const EXPECTED: &str = "expected \"stdio\" or { http-streaming = { port = <port> } }";
+    let is_http = |name: &str| matches!(name, "http-streaming" | "http" | "streamable-http" | "httpstreaming");
+
+    match RawTransport::deserialize(deserializer)? {
+      RawTransport::Name(name) => match normalize_name(&name).as_str() {
+        "stdio" => Ok(TransportType::Stdio),
+        other if is_http(other) => Err(D::Error::custom(format!(
+          "transport '{}' needs a port; {}",
+          name, EXPECTED
+        ))),
+        _ => Err(D::Error::custom(format!("unknown transport '{}'; {}", name, EXPECTED))),
+      },
+      RawTransport::WithOptions(options) => {
+        let mut options = options.into_iter();
+        match (options.next(), options.next()) {
+          (Some((name, HttpTransportOptions { port })), None) if is_http(&normalize_name(&name)) => {
+            Ok(TransportType::HttpStreaming { port })
+          }
+          (Some((name, _)), None) => {
+            Err(D::Error::custom(format!("unknown transport '{}'; {}", name, EXPECTED)))
+          }
+          _ => Err(D::Error::custom(format!("transport must name exactly one kind; {}", EXPECTED))),
+        }
+      }
+    }
+  }
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct AuditConfig {
+  /// URL that receives a JSON summary of every successful write tool call
+  pub webhook_url: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct ReviewConfig {
+  /// Prefix prepended to a comment body for each `severity`, added to or
+  /// replacing the built-in conventional-comment labels
+  #[serde(default)]
+  pub severity_prefixes: HashMap<String, String>,
+}
+
+/// Conventional Comments labels (https://conventionalcomments.org) offered
+/// as `severity` values out of the box.
+const DEFAULT_SEVERITIES: [&str; 9] = [
+  "praise", "nitpick", "suggestion", "issue", "todo", "question", "thought", "chore", "note",
+];
+
+impl ReviewConfig {
+  /// The prefix for `severity` (case-insensitive): a configured one, or
+  /// `**label:**` for a built-in label. `None` for an unknown severity.
+  pub fn severity_prefix(&self, severity: &str) -> Option<String> {
+    let severity = severity.trim().to_lowercase();
+    self
+      .severity_prefixes
+      .iter()
+      .find(|(name, _)| name.to_lowercase() == severity)
+      .map(|(_, prefix)| prefix.clone())
+      .or_else(|| {
+        DEFAULT_SEVERITIES
+          .contains(&severity.as_str())
+          .then(|| format!("**{}:**", severity))
+      })
+  }
+
+  /// Every accepted severity, sorted, for error messages.
+  pub fn severities(&self) -> Vec<String> {
+    let mut names: Vec<String> = DEFAULT_SEVERITIES.iter().map(|name| name.to_string()).collect();
+    names.extend(self.severity_prefixes.keys().map(|name| name.to_lowercase()));
+    names.sort();
+    names.dedup();
+    names
+  }
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct GitLabConfig {
+  /// Headers added to every GitLab request, e.g. for a gateway in front of
+  /// GitLab. `PRIVATE-TOKEN` and `Authorization` cannot be set here.
+  #[serde(default)]
+  pub extra_headers: HashMap<String, String>,
+  /// Where merge request file diffs are read from
+  #[serde(default)]
+  pub diffs_endpoint: DiffsEndpoint,
+  /// User agent sent to GitLab instead of `gitlab-mcp/<version>`
+  #[serde(default)]
+  pub user_agent: Option<String>,
+  #[serde(flatten)]
+  pub connection: ConnectionConfig,
+}
+
+/// Connection pool and protocol settings for the GitLab HTTP client. The
+/// defaults keep connections to the single GitLab host open for reuse, and
+/// HTTPS connections negotiate HTTP/2 when the server offers it.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct ConnectionConfig {
+  /// Idle connections kept open per host; unlimited when unset
+  #[serde(default)]
+  pub pool_max_idle_per_host: Option<usize>,
+  /// Seconds an idle pooled connection is kept before closing (default 90)
+  #[serde(default)]
+  pub pool_idle_timeout_secs: Option<u64>,
+  /// Seconds between TCP keepalive probes on open connections (default 60)
+  #[serde(default)]
+  pub tcp_keepalive_secs: Option<u64>,
+  /// Speak HTTP/2 without negotiating it first. Only for servers, or plain
+  /// HTTP endpoints, known to accept HTTP/2; HTTP/1.1-only proxies reject it.
+  #[serde(default)]
+  pub http2_prior_knowledge: bool,
+  /// Skip TLS certificate verification. Never read from the config file:
+  /// only `--insecure` or `GITLAB_INSECURE=1` turn it on.
+  #[serde(skip)]
+  pub accept_invalid_certs: bool,
+}
+
+/// The endpoint `get_merge_request_changes` reads file diffs from. GitLab
+/// 15.7 deprecated `/changes` in favor of the paginated `/diffs`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DiffsEndpoint {
+  /// `/diffs` when the instance reports GitLab 15.7 or newer, else `/changes`
+  #[default]
+  Auto,
+  Changes,
+  Diffs,
+}
+
+impl FromStr for DiffsEndpoint {
+  type Err = String;
+
+  fn from_str(value: &str) -> Result<Self, Self::Err> {
+    match normalize_name(value).as_str() {
+      "auto" => Ok(DiffsEndpoint::Auto),
+      "changes" => Ok(DiffsEndpoint::Changes),
+      "diffs" => Ok(DiffsEndpoint::Diffs),
+      _ => Err(format!(
+        "unknown diffs endpoint '{}'; expected \"auto\", \"changes\", or \"diffs\"",
+        value
+      )),
+    }
+  }
+}
+
+impl<'de> Deserialize<'de> for DiffsEndpoint {
+  fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+    String::deserialize(deserializer)?.parse().map_err(D::Error::custom)
+  }
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
 pub struct TelemetryConfig {
   pub level: String,
   pub format: LogFormat,
   pub file: Option<String>,
+  /// Rotate the log file once it grows past this many megabytes
+  #[serde(default)]
+  pub max_file_size_mb: Option<u64>,
+  /// Number of rotated log files (`.1`, `.2`, ...) to keep
+  #[serde(default)]
+  pub max_files: Option<usize>,
+  /// Force ANSI colors on or off; by default they are used only when
+  /// logging to a terminal and `NO_COLOR` is unset
+  #[serde(default)]
+  pub color: Option<bool>,
 }
 
-#[derive(Debug, Deserialize, Clone)]
-#[serde(rename_all = "lowercase")]
+impl TelemetryConfig {
+  pub fn max_file_bytes(&self) -> u64 {
+    self.max_file_size_mb.unwrap_or(10).max(1) * 1024 * 1024
+  }
+
+  pub fn max_files(&self) -> usize {
+    self.max_files.unwrap_or(5)
+  }
+}
+
+#[derive(Debug, Clone, Default)]
 pub enum LogFormat {
+  #[default]
   Pretty,
   Json,
 }
 
+impl FromStr for LogFormat {
+  type Err = String;
+
+  fn from_str(value: &str) -> Result<Self, Self::Err> {
+    match normalize_name(value).as_str() {
+      "pretty" | "text" => Ok(LogFormat::Pretty),
+      "json" => Ok(LogFormat::Json),
+      _ => Err(format!("unknown log format '{}'; expected \"pretty\" or \"json\"", value)),
+    }
+  }
+}
+
+impl<'de> Deserialize<'de> for LogFormat {
+  fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+    String::deserialize(deserializer)?.parse().map_err(D::Error::custom)
+  }
+}
+
 #[cfg(feature = "auth")]
 #[derive(Debug, Deserialize, Clone)]
 pub struct RedisConfig {
@@ -53,83 +396,178 @@ pub struct DatabaseConfig {
   pub max_connections: u32,
 }
 
-impl Config {
-  pub fn load() -> Result<Self, ConfigError> {
-    // Check for --http-port argument
-    let args: Vec<String> = std::env::args().collect();
-    let mut http_port: Option<u16> = None;
-
-    for i in 0..args.len() {
-      if args[i] == "--http-port" && i + 1 < args.len() {
-        if let Ok(port) = args[i + 1].parse::<u16>() {
-          http_port = Some(port);
-        }
+/// Replaces `${VAR}` in every string config value with the environment
+/// variable's value, so secrets can stay out of `config.toml`.
+fn expand_env_vars(value: &mut serde_json::Value) -> Result<(), ConfigError> {
+  match value {
+    serde_json::Value::String(text) => *text = expand_env_string(text)?,
+    serde_json::Value::Array(items) => {
+      for item in items {
+        expand_env_vars(item)?;
+      }
+    }
+    serde_json::Value::Object(map) => {
+      for item in map.values_mut() {
+        expand_env_vars(item)?;
       }
     }
+    _ => {}
+  }
+  Ok(())
+}
 
-    // Check for config files
-    let config_path = if Path::new("config.toml").exists() {
-      Some("config.toml")
-    } else if Path::new("/config.toml").exists() {
-      Some("/config.toml")
-    } else {
-      None
-    };
+fn expand_env_string(text: &str) -> Result<String, ConfigError> {
+  let mut expanded = String::with_capacity(text.len());
+  let mut rest = text;
+  while let Some(start) = rest.find("${") {
+    expanded.push_str(&rest[..start]);
+    let after = &rest[start + 2..];
+    let end = after
+      .find('}')
+      .ok_or_else(|| ConfigError::Message(format!("Unterminated ${{ in config value '{}'", text)))?;
+    let name = &after[..end];
+    let value = std::env::var(name).map_err(|_| {
+      ConfigError::Message(format!("Config references ${{{}}} but that environment variable is not set", name))
+    })?;
+    expanded.push_str(&value);
+    rest = &after[end + 1..];
+  }
+  expanded.push_str(rest);
+  Ok(expanded)
+}
 
-    // If we have a config file, use it
-    if let Some(path) = config_path {
-      tracing::info!("Loading config from: {}", path);
-      let config = ConfigBuilder::builder()
-        .add_source(File::with_name(path))
-        .build()?;
+/// The config file to load. A `--config` path must exist; otherwise the first
+/// of `./config.toml`, `$XDG_CONFIG_HOME/gitlab-mcp/config.toml`,
+/// `~/.config/gitlab-mcp/config.toml`, and `/config.toml` that exists wins.
+fn find_config_file(cli_path: Option<PathBuf>) -> Result<Option<PathBuf>, ConfigError> {
+  if let Some(path) = cli_path {
+    if !path.is_file() {
+      return Err(ConfigError::Message(format!("Config file {} does not exist", path.display())));
+    }
+    return Ok(Some(path));
+  }
+
+  let app_config = Path::new(env!("CARGO_PKG_NAME")).join("config.toml");
+  let candidates = [
+    Some(PathBuf::from("config.toml")),
+    std::env::var_os("XDG_CONFIG_HOME")
+      .filter(|dir| !dir.is_empty())
+      .map(|dir| PathBuf::from(dir).join(&app_config)),
+    dirs::home_dir().map(|home| home.join(".config").join(&app_config)),
+    Some(PathBuf::from("/config.toml")),
+  ];
+  Ok(candidates.into_iter().flatten().find(|path| path.is_file()))
+}
 
-      let mut config: Config = config.try_deserialize()?;
+/// Fallback log file for stdio transport, in the platform's temp directory.
+fn default_log_file() -> String {
+  std::env::temp_dir()
+    .join(format!("{}.log", env!("CARGO_PKG_NAME")))
+    .to_string_lossy()
+    .into_owned()
+}
 
-      // Force logging to file for stdio transport
-      if matches!(config.server.transport, TransportType::Stdio) && config.telemetry.file.is_none() {
-        config.telemetry.file = Some(format!("/tmp/{}.log", env!("CARGO_PKG_NAME")));
+/// Environment variable holding a whole TOML config, for containers that
+/// inject config without mounting a file.
+pub const CONFIG_ENV_VAR: &str = "GITLAB_MCP_CONFIG";
+
+impl Config {
+  pub fn load(cli: &Cli) -> Result<Self, ConfigError> {
+    let from_stdin = cli.config.as_deref() == Some(Path::new("-"));
+    let mut config = if from_stdin {
+      let mut source = String::new();
+      std::io::Read::read_to_string(&mut std::io::stdin(), &mut source)
+        .map_err(|err| ConfigError::Message(format!("Failed to read config from stdin: {}", err)))?;
+      Self::from_toml(&source, "stdin")?
+    } else if let Some(source) = std::env::var(CONFIG_ENV_VAR).ok().filter(|_| cli.config.is_none()) {
+      Self::from_toml(&source, CONFIG_ENV_VAR)?
+    } else {
+      match find_config_file(cli.config.clone())? {
+        Some(path) => Self::from_file(&path)?,
+        None => Self::from_env(),
       }
+    };
+    config.apply_cli(cli)?;
 
-      return Ok(config);
+    if from_stdin && matches!(config.server.transport, TransportType::Stdio) {
+      return Err(ConfigError::Message(
+        "--config - reads the config from stdin, which the stdio transport needs for MCP messages; \
+use GITLAB_MCP_CONFIG instead"
+          .to_string(),
+      ));
     }
 
-    // No config file - build from defaults/environment
-    let transport = if let Some(port) = http_port {
-      tracing::info!("No config file found, using HTTP streaming on port {} (from --http-port)", port);
-      TransportType::HttpStreaming { port }
-    } else if let Ok(port_str) = std::env::var("PORT") {
-      if let Ok(port) = port_str.parse::<u16>() {
+    if let Ok(path) = std::env::var("MCP_LOG_FILE") {
+      config.telemetry.file = Some(path);
+    }
+
+    // Force logging to file for stdio transport
+    if matches!(config.server.transport, TransportType::Stdio) && config.telemetry.file.is_none() {
+      config.telemetry.file = Some(default_log_file());
+    }
+
+    Ok(config)
+  }
+
+  fn from_file(path: &Path) -> Result<Self, ConfigError> {
+    tracing::info!("Loading config from: {}", path.display());
+    let config = ConfigBuilder::builder()
+      .add_source(File::from(path))
+      .build()?;
+    Self::from_builder(config, &path.display().to_string())
+  }
+
+  /// Parses a TOML config held in memory; `origin` names it in errors.
+  fn from_toml(source: &str, origin: &str) -> Result<Self, ConfigError> {
+    tracing::info!("Loading config from {}", origin);
+    let config = ConfigBuilder::builder()
+      .add_source(File::from_str(source, FileFormat::Toml))
+      .build()
+      .map_err(|err| ConfigError::Message(format!("{}: {}", origin, err)))?;
+    Self::from_builder(config, origin)
+  }
+
+  fn from_builder(config: ConfigBuilder, origin: &str) -> Result<Self, ConfigError> {
+    let mut raw: serde_json::Value = config.try_deserialize()?;
+    expand_env_vars(&mut raw)?;
+    serde_json::from_value(raw)
+      .map_err(|err| ConfigError::Message(format!("{}: {}", origin, err)))
+  }
+
+  /// Configuration used when no config file exists, built from defaults and
+  /// environment variables.
+  fn from_env() -> Self {
+    let transport = match std::env::var("PORT").ok().and_then(|port| port.parse::<u16>().ok()) {
+      Some(port) => {
         tracing::info!("No config file found, using HTTP streaming on port {} (from PORT env)", port);
         TransportType::HttpStreaming { port }
-      } else {
+      }
+      None => {
         tracing::info!("No config file found, using default stdio configuration");
         TransportType::Stdio
       }
-    } else {
-      tracing::info!("No config file found, using default stdio configuration");
-      TransportType::Stdio
     };
 
-    // Set log file for stdio transport
-    let log_file = if matches!(transport, TransportType::Stdio) {
-      Some(format!("/tmp/{}.log", env!("CARGO_PKG_NAME")))
-    } else {
-      None
-    };
-
-    Ok(Config {
+    Config {
       server: ServerConfig {
         name: env!("CARGO_PKG_NAME").to_string(),
         transport,
+        ..Default::default()
       },
       telemetry: TelemetryConfig {
         level: std::env::var("MCP_TELEMETRY_LEVEL").unwrap_or_else(|_| "info".to_string()),
-        format: match std::env::var("MCP_TELEMETRY_FORMAT").as_deref() {
-          Ok("json") => LogFormat::Json,
-          _ => LogFormat::Pretty,
-        },
-        file: log_file,
+        format: std::env::var("MCP_TELEMETRY_FORMAT")
+          .ok()
+          .and_then(|format| format.parse().ok())
+          .unwrap_or(LogFormat::Pretty),
+        file: None,
+        max_file_size_mb: std::env::var("MCP_LOG_MAX_SIZE_MB").ok().and_then(|size| size.parse().ok()),
+        max_files: std::env::var("MCP_LOG_MAX_FILES").ok().and_then(|count| count.parse().ok()),
+        color: None,
       },
+      audit: AuditConfig::default(),
+      gitlab: GitLabConfig::default(),
+      review: ReviewConfig::default(),
       #[cfg(feature = "auth")]
       redis: std::env::var("MCP_REDIS_URL")
         .or_else(|_| std::env::var("REDIS_URL"))
@@ -140,6 +578,121 @@ impl Config {
         url,
         max_connections: 10,
       }),
-    })
+    }
+  }
+
+  /// Command-line flags override both the config file and the environment.
+  fn apply_cli(&mut self, cli: &Cli) -> Result<(), ConfigError> {
+    if let Some(level) = &cli.log_level {
+      self.telemetry.level = level.clone();
+    }
+
+    if cli.insecure || std::env::var("GITLAB_INSECURE").is_ok_and(|value| is_truthy(&value)) {
+      self.gitlab.connection.accept_invalid_certs = true;
+    }
+
+    self.server.transport = match (cli.transport, cli.http_port) {
+      (Some(CliTransport::Stdio), Some(_)) => {
+        return Err(ConfigError::Message("--http-port cannot be used with --transport stdio".to_string()));
+      }
+      (Some(CliTransport::Stdio), None) => TransportType::Stdio,
+      (_, Some(port)) => {
+        tracing::info!("Using HTTP streaming on port {} (from --http-port)", port);
+        TransportType::HttpStreaming { port }
+      }
+      (Some(CliTransport::HttpStreaming), None) => match &self.server.transport {
+        TransportType::HttpStreaming { port } => TransportType::HttpStreaming { port: *port },
+        TransportType::Stdio => {
+          let port = std::env::var("PORT").ok().and_then(|port| port.parse().ok()).ok_or_else(|| {
+            ConfigError::Message("--transport http-streaming needs a port; pass --http-port".to_string())
+          })?;
+          TransportType::HttpStreaming { port }
+        }
+      },
+      (None, None) => return Ok(()),
+    };
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::time::Duration;
+
+  #[test]
+  fn requested_tool_timeout_can_only_shorten_the_configured_one() {
+    let limited = ServerConfig { tool_timeout_secs: Some(60), ..Default::default() };
+    assert_eq!(limited.tool_timeout(None), Some(Duration::from_secs(60)));
+    assert_eq!(limited.tool_timeout(Some(10)), Some(Duration::from_secs(10)));
+    assert_eq!(limited.tool_timeout(Some(600)), Some(Duration::from_secs(60)));
+    assert_eq!(limited.tool_timeout(Some(0)), Some(Duration::from_secs(60)));
+
+    let unlimited = ServerConfig::default();
+    assert_eq!(unlimited.tool_timeout(None), None);
+    assert_eq!(unlimited.tool_timeout(Some(0)), None);
+    assert_eq!(unlimited.tool_timeout(Some(30)), Some(Duration::from_secs(30)));
+  }
+
+  #[test]
+  fn expand_env_string_substitutes_set_variables() {
+    std::env::set_var("GITLAB_MCP_TEST_EXPAND_TOKEN", "secret");
+    assert_eq!(expand_env_string("no variables here").unwrap(), "no variables here");
+    assert_eq!(expand_env_string("${GITLAB_MCP_TEST_EXPAND_TOKEN}").unwrap(), "secret");
+    assert_eq!(
+      expand_env_string("Bearer ${GITLAB_MCP_TEST_EXPAND_TOKEN}!").unwrap(),
+      "Bearer secret!"
+    );
+  }
+
+  #[test]
+  fn expand_env_string_rejects_unset_and_unterminated_variables() {
+    let unset = expand_env_string("${GITLAB_MCP_TEST_EXPAND_UNSET}").unwrap_err();
+    assert!(unset
+      .to_string()
+      .contains("Config references ${GITLAB_MCP_TEST_EXPAND_UNSET} but that environment variable is not set"));
+
+    let unterminated = expand_env_string("token ${GITLAB_MCP_TEST_EXPAND_TOKEN").unwrap_err();
+    assert!(unterminated.to_string().contains("Unterminated ${ in config value"));
+  }
+
+  #[test]
+  fn transport_accepts_aliases_and_requires_a_port_for_http() {
+    let stdio: TransportType = serde_json::from_value(serde_json::json!("STDIO")).unwrap();
+    assert!(matches!(stdio, TransportType::Stdio));
+
+    let http: TransportType = serde_json::from_value(serde_json::json!({ "http": { "port": 8080 } })).unwrap();
+    assert!(matches!(http, TransportType::HttpStreaming { port: 8080 }));
+
+    let no_port = serde_json::from_value::<TransportType>(serde_json::json!("HTTP-Streaming")).unwrap_err();
+    assert!(no_port.to_string().contains("transport 'HTTP-Streaming' needs a port"));
+
+    let unknown = serde_json::from_value::<TransportType>(serde_json::json!("sse")).unwrap_err();
+    assert!(unknown.to_string().contains("unknown transport 'sse'"));
+
+    let two = serde_json::from_value::<TransportType>(serde_json::json!({
+      "http": { "port": 1 },
+      "streamable-http": { "port": 2 }
+    }))
+    .unwrap_err();
+    assert!(two.to_string().contains("transport must name exactly one kind"));
+  }
+
+  #[test]
+  fn named_options_parse_case_insensitively() {
+    assert!(matches!("JSON".parse::<LogFormat>(), Ok(LogFormat::Json)));
+    assert!(matches!("text".parse::<LogFormat>(), Ok(LogFormat::Pretty)));
+    assert_eq!(
+      "yaml".parse::<LogFormat>().unwrap_err(),
+      "unknown log format 'yaml'; expected \"pretty\" or \"json\""
+    );
+
+    assert_eq!("Diffs".parse(), Ok(DiffsEndpoint::Diffs));
+    assert_eq!("auto".parse(), Ok(DiffsEndpoint::Auto));
+    assert!("patch".parse::<DiffsEndpoint>().unwrap_err().contains("unknown diffs endpoint 'patch'"));
+
+    assert_eq!("ERROR".parse(), Ok(OversizeResponse::Error));
+    assert_eq!("truncate".parse(), Ok(OversizeResponse::Truncate));
+    assert!("drop".parse::<OversizeResponse>().unwrap_err().contains("unknown oversize response 'drop'"));
   }
 }
@@ -1,5 +1,7 @@
 #[cfg(feature = "auth")]
 pub mod auth;
+pub mod audit;
+pub mod cli;
 pub mod config;
 pub mod error;
 pub mod gitlab;
@@ -7,30 +9,268 @@ pub mod tools;
 pub mod state;
 pub mod telemetry;
 
+use std::future::IntoFuture;
 use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use futures::stream::{self, StreamExt};
 use rmcp::{
-  ServerHandler, ServiceExt,
-  tool, tool_handler, tool_router
+  Peer, RoleServer, ServerHandler, ServiceExt,
+  tool, tool_router
 };
 use rmcp::transport::{stdio, streamable_http_server::{StreamableHttpService, StreamableHttpServerConfig}};
 use rmcp::model::{*, ErrorData as McpError};
-use rmcp::handler::server::{router::tool::ToolRouter, wrapper::Parameters};
+use rmcp::handler::server::{router::tool::ToolRouter, tool::ToolCallContext, wrapper::Parameters};
+use rmcp::service::RequestContext;
+use tokio_util::sync::CancellationToken;
 use tower::Service;
+use serde_json::Value;
 
-use crate::config::Config;
-use crate::state::ServerState;
+use crate::audit::AuditEvent;
+use crate::config::{Config, OversizeResponse};
+use crate::gitlab::{is_dry_run_response, token_warnings, GitLabClient, PageWalk, Progress, DEFAULT_USER_AGENT};
+use crate::state::{Idempotency, ServerState};
 use crate::tools::gitlab::{
+  AddSpentTimeRequest,
+  ApplySuggestionRequest,
+  BatchDiscussion,
+  CommitOntoBranchRequest,
+  CompareRefsRequest,
+  CreateCommitRequest,
+  CreateIssueLinkRequest,
   CreateMergeRequestDiscussionRequest,
+  CreateMergeRequestDiscussionsBatchRequest,
   CreateMergeRequestNoteRequest,
+  CreateReleaseRequest,
+  CreateTagRequest,
+  DeleteIssueLinkRequest,
+  GetJobLogRequest,
+  GetMergeRequestApprovalStateRequest,
+  GetMergeRequestChangesPageRequest,
   GetMergeRequestChangesRequest,
+  GetMergeRequestConflictsRequest,
+  GetMergeRequestDiffRefsRequest,
+  GetMergeRequestDiffStatsRequest,
+  GetMergeRequestDiffsRequest,
+  GetMergeRequestEventsRequest,
+  GetMergeRequestMergeReadinessRequest,
+  GetMergeRequestRawDiffRequest,
   GetMergeRequestRequest,
+  GetMergeRequestVersionDiffRequest,
   GetMergeRequestVersionsRequest,
+  GetPipelineFailuresRequest,
+  GetPipelineTestReportRequest,
+  GetProjectRequest,
+  GetReleaseRequest,
+  GetRepositoryFileRequest,
+  GetReviewBacklogRequest,
+  GetUserRequest,
+  GitLabRestRequest,
+  GlobalSearchRequest,
+  JobScope,
+  ListMembersRequest,
+  ListMergeRequestDiscussionsRequest,
+  ListMergeRequestsRequest,
+  ListMilestonesRequest,
+  ListMyReviewRequestsRequest,
+  ListProjectJobsRequest,
+  ListReleasesRequest,
+  ListTagsRequest,
+  ListTodosRequest,
+  MarkTodoDoneRequest,
+  MergeMergeRequestRequest,
+  MergeRequest,
   MergeRequestLocator,
+  MergeRequestStateRequest,
+  MoveIssueRequest,
+  ProjectLocator,
+  ProjectSearchRequest,
+  QuoteReplyRequest,
+  SetMergeRequestDraftRequest,
+  SetTimeEstimateRequest,
+  StateEvent,
+  SummarizeMergeRequestRequest,
+  UpdateMergeRequestReviewersRequest,
+  WriteOptions,
+  DEFAULT_MAX_ITEMS,
+  MAX_BATCH_DISCUSSIONS,
+  MAX_CONTENT_CHUNK_BYTES,
   json_result,
+  project_page,
+  project_value,
   discussion_payload,
   note_payload,
+  release_payload,
+  tag_payload,
+  reviewer_ids,
+  reviewers_payload,
+  reviewers_result,
+  commit_onto_branch_payload,
+  commit_payload,
+  apply_suggestion_payload,
+  failed_test_report,
+  json_result_chunked,
+  truncate_changes,
+  with_note_web_url,
+  merge_readiness,
+  filter_changes,
+  changes_page,
+  validate_position_in_changes,
+  batch_item_result,
+  quote_reply_body,
+  merge_payload,
+  unresolved_discussion_ids,
+  decode_file_content,
+  compact_merge_requests,
+  text_result_chunked,
+  diff_stats,
+  check_state_event,
+  state_event_payload,
+  compact_merge_request,
+  draft_title,
+  draft_result,
+  subscription_result,
+  validate_duration,
+  review_backlog,
+  unresolved_discussions,
+  filter_jobs_by_name,
+  pipeline_failures,
+  merge_request_summary,
+  apply_severity,
+  overlapping_files,
+  result_size,
+  truncate_result,
 };
 
+/// Tools that create, update, or delete anything on GitLab. Read-only mode
+/// leaves these out of the router entirely. `gitlab_rest` is listed because
+/// it can write, but its GET calls are not audited as writes.
+pub const WRITE_TOOLS: &[&str] = &[
+  "create_merge_request_discussion",
+  "create_merge_request_discussions_batch",
+  "create_merge_request_note",
+  "quote_reply",
+  "create_release",
+  "create_tag",
+  "assign_merge_request_reviewers",
+  "unassign_merge_request_reviewers",
+  "cherry_pick_commit",
+  "revert_commit",
+  "create_commit",
+  "merge_merge_request",
+  "apply_suggestion",
+  "close_merge_request",
+  "reopen_merge_request",
+  "set_merge_request_draft",
+  "subscribe_merge_request",
+  "unsubscribe_merge_request",
+  "set_merge_request_time_estimate",
+  "add_merge_request_spent_time",
+  "move_issue",
+  "create_issue_link",
+  "delete_issue_link",
+  "mark_todo_done",
+  "gitlab_rest",
+];
+
+/// Guidance sent to clients in `initialize` unless `server.instructions`
+/// replaces it.
+pub const DEFAULT_INSTRUCTIONS: &str = "GitLab merge request review tools. Set GITLAB_URL (without /api/v4) and GITLAB_TOKEN before launch. Workflow: (1) get_merge_request for metadata and get_merge_request_changes for diff context; (2) get_merge_request_diff_refs for the base/head/start SHAs; (3) call create_merge_request_discussion with body markdown and a position JSON containing: base_sha, head_sha, start_sha, new_path, old_path, and line numbers (new_line for additions, old_line for deletions). The position_type field defaults to 'text' if not specified. Use create_merge_request_note for top-level MR comments. Any call may set _meta.timeout_secs to fail sooner than the server's own deadline.";
+
+/// Write tools that can move a merge request's branch head, and so make
+/// cached changes and diff_refs stale.
+const DIFF_CHANGING_TOOLS: &[&str] = &[
+  "cherry_pick_commit",
+  "revert_commit",
+  "create_commit",
+  "apply_suggestion",
+  "merge_merge_request",
+  "gitlab_rest",
+];
+
+/// The project and merge request IID a call's arguments name, parsed the way
+/// the tools parse them, so merge request URLs and `namespace`/`name` resolve
+/// to a project path. `None` for whatever the arguments do not name.
+fn call_target(arguments: Option<&JsonObject>) -> (Option<String>, Option<u64>) {
+  let Some(arguments) = arguments.map(|arguments| Value::Object(arguments.clone())) else {
+    return (None, None);
+  };
+  if let Ok(MergeRequestLocator { project, merge_request_iid }) = serde_json::from_value(arguments.clone()) {
+    return (Some(project), Some(merge_request_iid));
+  }
+  match serde_json::from_value::<ProjectLocator>(arguments) {
+    Ok(ProjectLocator { project }) => (Some(project), None),
+    Err(_) => (None, None),
+  }
+}
+
+/// `_meta` key set on a result replayed for an idempotency key.
+const REPLAYED_META_KEY: &str = "idempotent_replay";
+
+/// Marks a result as replayed for an idempotency key: nothing was posted, so
+/// `call_tool` audits it as `replayed` rather than as another write.
+fn replayed(result: Result<CallToolResult, McpError>) -> Result<CallToolResult, McpError> {
+  result.map(|mut result| {
+    let mut meta = result.meta.take().unwrap_or_default();
+    meta.insert(REPLAYED_META_KEY.to_string(), Value::Bool(true));
+    result.meta = Some(meta);
+    result
+  })
+}
+
+fn is_replayed(result: &CallToolResult) -> bool {
+  result
+    .meta
+    .as_ref()
+    .and_then(|meta| meta.get(REPLAYED_META_KEY))
+    .and_then(Value::as_bool)
+    .unwrap_or(false)
+}
+
+/// Whether a call to `tool` with `arguments` writes to GitLab, for auditing
+/// and cache invalidation. `gitlab_rest` only writes when its method is not
+/// GET; an unparseable method is treated as a write.
+fn is_write_call(tool: &str, arguments: Option<&JsonObject>) -> bool {
+  if tool == "gitlab_rest" {
+    let method = arguments.and_then(|arguments| arguments.get("method")).and_then(Value::as_str);
+    return method != Some("GET");
+  }
+  WRITE_TOOLS.contains(&tool)
+}
+
+/// Resolves on Ctrl-C, or on SIGTERM on unix, which is what `docker stop`
+/// and Kubernetes send before killing the process.
+async fn shutdown_signal() {
+  let ctrl_c = async {
+    if let Err(err) = tokio::signal::ctrl_c().await {
+      tracing::error!("Failed to listen for Ctrl-C: {}", err);
+      std::future::pending::<()>().await;
+    }
+  };
+  #[cfg(unix)]
+  let terminate = async {
+    match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+      Ok(mut signal) => {
+        signal.recv().await;
+      }
+      Err(err) => {
+        tracing::error!("Failed to listen for SIGTERM: {}", err);
+        std::future::pending::<()>().await;
+      }
+    }
+  };
+  #[cfg(not(unix))]
+  let terminate = std::future::pending::<()>();
+
+  tokio::select! {
+    _ = ctrl_c => {}
+    _ = terminate => {}
+  }
+}
+
+/// How many GitLab writes a batch tool keeps in flight at once.
+const BATCH_CONCURRENCY: usize = 4;
+
 #[derive(Clone)]
 pub struct Server {
   config: Config,
@@ -41,81 +281,1310 @@ pub struct Server {
 
 #[tool_router]
 impl Server {
-  #[tool(description = "Fetch metadata for a GitLab merge request (title, author, state, approvals, etc.)")]
+  #[tool(description = "Fetch metadata for a GitLab merge request: iid, title, description, state, author, source/target branch, draft, merge status, labels, web_url, and diff_refs. Set include_raw for GitLab's full object.")]
   pub async fn get_merge_request(
     &self,
-    Parameters(req): Parameters<GetMergeRequestRequest>,
+    Parameters(req): Parameters<GetMergeRequestRequest>,
+  ) -> Result<CallToolResult, McpError>{
+    let MergeRequestLocator { project, merge_request_iid } = req.locator;
+    let value = self
+      .state
+      .gitlab
+      .get_merge_request(&project, merge_request_iid)
+      .await?;
+    let value = if req.include_raw.unwrap_or(false) {
+      value
+    } else {
+      MergeRequest::from_value(value)?.into_value()
+    };
+
+    json_result(project_value(value, req.projection.fields.as_deref()))
+  }
+
+  #[tool(description = "Fetch the diff changes for a GitLab merge request (file list and hunks). Set max_bytes to replace the largest file diffs with a truncation marker when the MR is too big.")]
+  pub async fn get_merge_request_changes(
+    &self,
+    Parameters(req): Parameters<GetMergeRequestChangesRequest>,
+  ) -> Result<CallToolResult, McpError>{
+    let MergeRequestLocator { project, merge_request_iid } = req.locator;
+    let mut value = self
+      .state
+      .merge_request_changes(&project, merge_request_iid)
+      .await?;
+    if let Some(paths) = &req.paths {
+      value = filter_changes(value, paths);
+    }
+    if let Some(max_bytes) = req.max_bytes {
+      value = truncate_changes(value, max_bytes);
+    }
+
+    json_result_chunked(
+      project_value(value, req.projection.fields.as_deref()),
+      MAX_CONTENT_CHUNK_BYTES,
+    )
+  }
+
+  #[tool(description = "Fetch merge request versions (base/head/start commit SHAs for discussions)")]
+  pub async fn get_merge_request_versions(
+    &self,
+    Parameters(req): Parameters<GetMergeRequestVersionsRequest>,
+  ) -> Result<CallToolResult, McpError>{
+    let MergeRequestLocator { project, merge_request_iid } = req.locator;
+    let value = self
+      .state
+      .gitlab
+      .get_merge_request_versions(&project, merge_request_iid)
+      .await?;
+
+    json_result(project_value(value, req.projection.fields.as_deref()))
+  }
+
+  #[tool(description = "Fetch the diffs of one merge request version (version_id from get_merge_request_versions), to see what a single push changed")]
+  pub async fn get_merge_request_version_diff(
+    &self,
+    Parameters(req): Parameters<GetMergeRequestVersionDiffRequest>,
+  ) -> Result<CallToolResult, McpError>{
+    let MergeRequestLocator { project, merge_request_iid } = req.locator;
+    let mut version = self
+      .state
+      .gitlab
+      .get_merge_request_version(&project, merge_request_iid, req.version_id)
+      .await?;
+    let diffs = version
+      .get_mut("diffs")
+      .map(Value::take)
+      .unwrap_or_else(|| Value::Array(Vec::new()));
+
+    json_result_chunked(
+      project_value(diffs, req.projection.fields.as_deref()),
+      MAX_CONTENT_CHUNK_BYTES,
+    )
+  }
+
+  #[tool(description = "Create a line-level discussion on a GitLab merge request. The position field requires: base_sha, head_sha, start_sha (from get_merge_request_versions), new_path, old_path, and line numbers (new_line for additions, old_line for deletions). Position can be a JSON object or string. The position_type defaults to 'text'.")]
+  pub async fn create_merge_request_discussion(
+    &self,
+    Parameters(mut req): Parameters<CreateMergeRequestDiscussionRequest>,
+  ) -> Result<CallToolResult, McpError>{
+    apply_severity(&mut req.body, req.severity.as_deref(), &self.config.review)?;
+    let payload = discussion_payload(&req)?;
+    let MergeRequestLocator { project, merge_request_iid } = req.locator;
+    if req.validate_position.unwrap_or(false) {
+      let changes = self
+        .state
+        .merge_request_changes(&project, merge_request_iid)
+        .await?;
+      validate_position_in_changes(&payload["position"], &changes)?;
+    }
+    let idempotency_key = req.idempotency_key.map(|key| {
+      format!("create_merge_request_discussion:{}:{}:{}", project, merge_request_iid, key)
+    });
+    let reservation = match idempotency_key {
+      Some(key) => match self.state.reserve_idempotency_key(key).await {
+        Idempotency::Replay(value) => {
+          return replayed(json_result(self.with_note_web_url(&project, merge_request_iid, value).await));
+        }
+        Idempotency::Reserved(reservation) => Some(reservation),
+      },
+      None => None,
+    };
+
+    let value = self
+      .gitlab(&req.write)
+      .create_merge_request_discussion(&project, merge_request_iid, payload)
+      .await?;
+    // Recorded before the web_url lookup, so a retry after a timeout from
+    // here on replays instead of posting again.
+    if let Some(reservation) = reservation.filter(|_| !is_dry_run_response(&value)) {
+      reservation.complete(value.clone());
+    }
+    let value = self.with_note_web_url(&project, merge_request_iid, value).await;
+
+    json_result(value)
+  }
+
+  #[tool(description = "Create a general note on a GitLab merge request (top-level discussion comment)")]
+  pub async fn create_merge_request_note(
+    &self,
+    Parameters(mut req): Parameters<CreateMergeRequestNoteRequest>,
+  ) -> Result<CallToolResult, McpError>{
+    apply_severity(&mut req.body, req.severity.as_deref(), &self.config.review)?;
+    let payload = note_payload(&req);
+    let MergeRequestLocator { project, merge_request_iid } = req.locator;
+    let idempotency_key = req.idempotency_key.map(|key| {
+      format!("create_merge_request_note:{}:{}:{}", project, merge_request_iid, key)
+    });
+    let reservation = match idempotency_key {
+      Some(key) => match self.state.reserve_idempotency_key(key).await {
+        Idempotency::Replay(value) => {
+          return replayed(json_result(self.with_note_web_url(&project, merge_request_iid, value).await));
+        }
+        Idempotency::Reserved(reservation) => Some(reservation),
+      },
+      None => None,
+    };
+
+    let value = self
+      .gitlab(&req.write)
+      .create_merge_request_note(&project, merge_request_iid, payload)
+      .await?;
+    if let Some(reservation) = reservation.filter(|_| !is_dry_run_response(&value)) {
+      reservation.complete(value.clone());
+    }
+    let value = self.with_note_web_url(&project, merge_request_iid, value).await;
+
+    json_result(value)
+  }
+
+  #[tool(description = "List project milestones (id, title, due_date, state). Optionally filter by state (active/closed) or search text.")]
+  pub async fn list_milestones(
+    &self,
+    Parameters(req): Parameters<ListMilestonesRequest>,
+  ) -> Result<CallToolResult, McpError>{
+    let ProjectLocator { project } = req.locator;
+    let value = self
+      .state
+      .gitlab
+      .list_milestones(
+        &project,
+        req.state.as_ref().map(|state| state.as_str()),
+        req.search.as_deref(),
+        req.pagination.page(),
+        req.pagination.per_page(),
+      )
+      .await?;
+
+    json_result(project_page(value, req.projection.fields.as_deref()))
+  }
+
+  #[tool(description = "List project releases, newest first (tag_name, name, description, released_at, assets)")]
+  pub async fn list_releases(
+    &self,
+    Parameters(req): Parameters<ListReleasesRequest>,
+  ) -> Result<CallToolResult, McpError>{
+    let ProjectLocator { project } = req.locator;
+    let value = self
+      .state
+      .gitlab
+      .list_releases(&project, req.pagination.page(), req.pagination.per_page())
+      .await?;
+
+    json_result(project_page(value, req.projection.fields.as_deref()))
+  }
+
+  #[tool(description = "Fetch a single project release by its tag name, including description, assets, and released_at")]
+  pub async fn get_release(
+    &self,
+    Parameters(req): Parameters<GetReleaseRequest>,
+  ) -> Result<CallToolResult, McpError>{
+    let ProjectLocator { project } = req.locator;
+    let value = self
+      .state
+      .gitlab
+      .get_release(&project, &req.tag_name)
+      .await?;
+
+    json_result(project_value(value, req.projection.fields.as_deref()))
+  }
+
+  #[tool(description = "Create a project release for a tag. Provide tag_name, name, and markdown description; pass ref (branch or SHA) to create the tag if it does not exist yet, and optional milestone titles.")]
+  pub async fn create_release(
+    &self,
+    Parameters(req): Parameters<CreateReleaseRequest>,
+  ) -> Result<CallToolResult, McpError>{
+    let payload = release_payload(&req)?;
+    let ProjectLocator { project } = req.locator;
+    let value = self
+      .gitlab(&req.write)
+      .create_release(&project, payload)
+      .await?;
+
+    json_result(value)
+  }
+
+  #[tool(description = "List repository tags, newest first. Optionally filter by search term and order by name, updated, or version.")]
+  pub async fn list_tags(
+    &self,
+    Parameters(req): Parameters<ListTagsRequest>,
+  ) -> Result<CallToolResult, McpError>{
+    let ProjectLocator { project } = req.locator;
+    let value = self
+      .state
+      .gitlab
+      .list_tags(
+        &project,
+        req.search.as_deref(),
+        req.order_by.as_ref().map(|order_by| order_by.as_str()),
+        req.sort.as_ref().map(|sort| sort.as_str()),
+        req.pagination.page(),
+        req.pagination.per_page(),
+      )
+      .await?;
+
+    json_result(project_page(value, req.projection.fields.as_deref()))
+  }
+
+  #[tool(description = "Create a repository tag from a branch or commit SHA (ref). Pass message to create an annotated tag. Use create_release instead when release notes are needed.")]
+  pub async fn create_tag(
+    &self,
+    Parameters(req): Parameters<CreateTagRequest>,
+  ) -> Result<CallToolResult, McpError>{
+    let ProjectLocator { project } = &req.locator;
+    let git_ref = self.resolve_ref(project, req.git_ref.as_deref()).await?;
+    let payload = tag_payload(&req, &git_ref)?;
+    let value = self
+      .gitlab(&req.write)
+      .create_tag(project, payload)
+      .await?;
+
+    json_result(value)
+  }
+
+  #[tool(description = "Add reviewers to a merge request by GitLab username. Existing reviewers are kept. Returns the updated reviewer list.")]
+  pub async fn assign_merge_request_reviewers(
+    &self,
+    Parameters(req): Parameters<UpdateMergeRequestReviewersRequest>,
+  ) -> Result<CallToolResult, McpError>{
+    let MergeRequestLocator { project, merge_request_iid } = req.locator;
+    let mut ids = reviewer_ids(
+      &self.state.gitlab.get_merge_request(&project, merge_request_iid).await?,
+    );
+    for username in &req.usernames {
+      let id = self.state.resolve_user_id(username).await?;
+      if !ids.contains(&id) {
+        ids.push(id);
+      }
+    }
+
+    let value = self
+      .gitlab(&req.write)
+      .update_merge_request(&project, merge_request_iid, reviewers_payload(&ids))
+      .await?;
+
+    json_result(reviewers_result(&value))
+  }
+
+  #[tool(description = "Remove reviewers from a merge request by GitLab username. Other reviewers are kept. Returns the updated reviewer list.")]
+  pub async fn unassign_merge_request_reviewers(
+    &self,
+    Parameters(req): Parameters<UpdateMergeRequestReviewersRequest>,
+  ) -> Result<CallToolResult, McpError>{
+    let MergeRequestLocator { project, merge_request_iid } = req.locator;
+    let mut ids = reviewer_ids(
+      &self.state.gitlab.get_merge_request(&project, merge_request_iid).await?,
+    );
+    for username in &req.usernames {
+      let id = self.state.resolve_user_id(username).await?;
+      ids.retain(|existing| *existing != id);
+    }
+
+    let value = self
+      .gitlab(&req.write)
+      .update_merge_request(&project, merge_request_iid, reviewers_payload(&ids))
+      .await?;
+
+    json_result(reviewers_result(&value))
+  }
+
+  #[tool(description = "Look up a GitLab user by username or numeric ID (exactly one). Username lookups return a matching user list; ID lookups return a single user object.")]
+  pub async fn get_user(
+    &self,
+    Parameters(req): Parameters<GetUserRequest>,
+  ) -> Result<CallToolResult, McpError>{
+    req.validate()?;
+    let value = match req.id {
+      Some(id) => self.state.gitlab.get_user(id).await?,
+      None => {
+        let username = req.username.unwrap_or_default();
+        self
+          .state
+          .gitlab
+          .find_users_by_username(username.trim().trim_start_matches('@'))
+          .await?
+      }
+    };
+
+    json_result(project_value(value, req.projection.fields.as_deref()))
+  }
+
+  #[tool(description = "Search within a project. scope is one of blobs (code), commits, issues, merge_requests, or notes. Use page/per_page to walk through large result sets.")]
+  pub async fn project_search(
+    &self,
+    Parameters(req): Parameters<ProjectSearchRequest>,
+  ) -> Result<CallToolResult, McpError>{
+    if req.search.trim().is_empty() {
+      return Err(McpError::invalid_params("search must not be empty", None));
+    }
+    let ProjectLocator { project } = req.locator;
+    let value = self
+      .state
+      .gitlab
+      .project_search(
+        &project,
+        req.scope.as_str(),
+        &req.search,
+        req.pagination.page(),
+        req.pagination.per_page(),
+      )
+      .await?;
+
+    json_result(project_page(value, req.projection.fields.as_deref()))
+  }
+
+  #[tool(description = "Search across every project the token can access. scope is one of blobs (code), commits, issues, merge_requests, notes, or projects. Useful for finding which project owns a file or symbol. Blob and commit scopes require GitLab advanced search. Unavailable when the server restricts which projects it may access.")]
+  pub async fn global_search(
+    &self,
+    Parameters(req): Parameters<GlobalSearchRequest>,
+  ) -> Result<CallToolResult, McpError>{
+    if req.search.trim().is_empty() {
+      return Err(McpError::invalid_params("search must not be empty", None));
+    }
+    let value = self
+      .state
+      .gitlab
+      .global_search(
+        req.scope.as_str(),
+        &req.search,
+        req.pagination.page(),
+        req.pagination.per_page(),
+      )
+      .await?;
+
+    json_result(project_page(value, req.projection.fields.as_deref()))
+  }
+
+  #[tool(description = "Fetch project metadata: default_branch, visibility, web_url, namespace, and more")]
+  pub async fn get_project(
+    &self,
+    Parameters(req): Parameters<GetProjectRequest>,
+  ) -> Result<CallToolResult, McpError>{
+    let ProjectLocator { project } = req.locator;
+    let value = self.state.refresh_project(&project).await?;
+
+    json_result(project_value(value, req.projection.fields.as_deref()))
+  }
+
+  #[tool(description = "List project members, including those inherited from parent groups, with username and access_level (10 guest, 20 reporter, 30 developer, 40 maintainer, 50 owner)")]
+  pub async fn list_members(
+    &self,
+    Parameters(req): Parameters<ListMembersRequest>,
+  ) -> Result<CallToolResult, McpError>{
+    let ProjectLocator { project } = req.locator;
+    let value = self
+      .state
+      .gitlab
+      .list_members(
+        &project,
+        req.query.as_deref(),
+        req.pagination.page(),
+        req.pagination.per_page(),
+      )
+      .await?;
+
+    json_result(project_page(value, req.projection.fields.as_deref()))
+  }
+
+  #[tool(description = "Cherry-pick a commit onto a branch (e.g. to backport a fix). Conflicts are reported as an invalid_params error with GitLab's detail.")]
+  pub async fn cherry_pick_commit(
+    &self,
+    Parameters(req): Parameters<CommitOntoBranchRequest>,
+  ) -> Result<CallToolResult, McpError>{
+    let payload = commit_onto_branch_payload(&req)?;
+    let ProjectLocator { project } = req.locator;
+    let value = self
+      .gitlab(&req.write)
+      .cherry_pick_commit(&project, &req.sha, payload)
+      .await?;
+
+    json_result(value)
+  }
+
+  #[tool(description = "Revert a commit on a branch by creating a new revert commit. Conflicts are reported as an invalid_params error with GitLab's detail.")]
+  pub async fn revert_commit(
+    &self,
+    Parameters(req): Parameters<CommitOntoBranchRequest>,
+  ) -> Result<CallToolResult, McpError>{
+    let payload = commit_onto_branch_payload(&req)?;
+    let ProjectLocator { project } = req.locator;
+    let value = self
+      .gitlab(&req.write)
+      .revert_commit(&project, &req.sha, payload)
+      .await?;
+
+    json_result(value)
+  }
+
+  #[tool(description = "Create a single commit with multiple file actions (create/update/delete/move) on a branch, without a local checkout. create and update need the full file content; move needs previous_path. Set start_branch to create the branch on the fly.")]
+  pub async fn create_commit(
+    &self,
+    Parameters(req): Parameters<CreateCommitRequest>,
+  ) -> Result<CallToolResult, McpError>{
+    let payload = commit_payload(&req)?;
+    let ProjectLocator { project } = req.locator;
+    let value = self
+      .gitlab(&req.write)
+      .create_commit(&project, payload)
+      .await?;
+
+    json_result(value)
+  }
+
+  #[tool(description = "Apply a merge request suggestion by ID, committing the suggested change to the source branch. Suggestion IDs appear in the suggestions array of discussion notes.")]
+  pub async fn apply_suggestion(
+    &self,
+    Parameters(req): Parameters<ApplySuggestionRequest>,
+  ) -> Result<CallToolResult, McpError>{
+    let payload = apply_suggestion_payload(&req);
+    let value = self
+      .gitlab(&req.write)
+      .apply_suggestion(req.suggestion_id, payload)
+      .await?;
+
+    json_result(value)
+  }
+
+  #[tool(description = "Fetch the structured test report for a pipeline (suites, test cases, failure messages and stack traces). Set failed_only to return just failing suites and test cases.")]
+  pub async fn get_pipeline_test_report(
+    &self,
+    Parameters(req): Parameters<GetPipelineTestReportRequest>,
+  ) -> Result<CallToolResult, McpError>{
+    let ProjectLocator { project } = req.locator;
+    let mut value = self
+      .state
+      .gitlab
+      .get_pipeline_test_report(&project, req.pipeline_id)
+      .await?;
+    if req.failed_only.unwrap_or(false) {
+      value = failed_test_report(value);
+    }
+
+    json_result_chunked(
+      project_value(value, req.projection.fields.as_deref()),
+      MAX_CONTENT_CHUNK_BYTES,
+    )
+  }
+
+  #[tool(description = "List a project's merge requests with optional state filter and ordering. Set all_pages to follow every page until max_items is reached; the last page is returned whole and next_page says where to resume. Add keyset for cursor-based pagination on very large projects.")]
+  pub async fn list_merge_requests(
+    &self,
+    Parameters(req): Parameters<ListMergeRequestsRequest>,
+    meta: Meta,
+    peer: Peer<RoleServer>,
+    cancel: CancellationToken,
+  ) -> Result<CallToolResult, McpError>{
+    req.validate()?;
+    let query = req.query();
+    let walk = req.page_walk();
+    let ProjectLocator { project } = req.locator;
+    let mut page = self
+      .state
+      .gitlab
+      .with_progress(Progress::new(&meta, peer))
+      .with_cancellation(cancel)
+      .list_merge_requests(&project, &query, walk)
+      .await?;
+    if req.compact.unwrap_or(false) {
+      page.items = compact_merge_requests(page.items);
+    }
+
+    json_result(project_page(page, req.projection.fields.as_deref()))
+  }
+
+  #[tool(description = "Report this server's name, version, transport, uptime, and GitLab API base URL")]
+  pub async fn server_status(&self) -> Result<CallToolResult, McpError>{
+    json_result(serde_json::json!({
+      "uptime_secs": self.state.uptime().as_secs(),
+      "version": env!("CARGO_PKG_VERSION"),
+      "name": self.config.server.name,
+      "transport": self.config.server.transport.as_str(),
+      "gitlab_base_url": self.state.gitlab.base_url(),
+      "user_agent": self.config.gitlab.user_agent.as_deref().unwrap_or(DEFAULT_USER_AGENT),
+    }))
+  }
+
+  #[tool(description = "Decide whether a merge request can be merged now. Combines the MR, its approvals, and its latest pipeline into mergeable_now plus a list of blockers (draft, conflicts, approvals left, pipeline status, unresolved discussions).")]
+  pub async fn get_merge_request_merge_readiness(
+    &self,
+    Parameters(req): Parameters<GetMergeRequestMergeReadinessRequest>,
+  ) -> Result<CallToolResult, McpError>{
+    let MergeRequestLocator { project, merge_request_iid } = req.locator;
+    let gitlab = &self.state.gitlab;
+    let (merge_request, approvals, pipeline) = tokio::join!(
+      gitlab.get_merge_request(&project, merge_request_iid),
+      gitlab.get_merge_request_approvals(&project, merge_request_iid),
+      gitlab.get_latest_merge_request_pipeline(&project, merge_request_iid),
+    );
+    let merge_request = merge_request?;
+    let approvals = approvals
+      .map_err(|err| tracing::warn!("Merge request approvals unavailable: {}", err.message))
+      .ok();
+    let pipeline = pipeline
+      .map_err(|err| tracing::warn!("Merge request pipelines unavailable: {}", err.message))
+      .ok()
+      .flatten();
+
+    json_result(project_value(
+      merge_readiness(&merge_request, approvals.as_ref(), pipeline.as_ref()),
+      req.projection.fields.as_deref(),
+    ))
+  }
+
+  #[tool(description = "Fetch merge request changes a few files at a time. Files are sorted by new_path; pass offset and limit to walk them, and follow next_offset until it is null. Returns total_files.")]
+  pub async fn get_merge_request_changes_page(
+    &self,
+    Parameters(req): Parameters<GetMergeRequestChangesPageRequest>,
+  ) -> Result<CallToolResult, McpError>{
+    let (offset, limit) = (req.offset(), req.limit());
+    let MergeRequestLocator { project, merge_request_iid } = req.locator;
+    let value = self
+      .state
+      .merge_request_changes(&project, merge_request_iid)
+      .await?;
+
+    json_result_chunked(
+      project_value(changes_page(value, offset, limit), req.projection.fields.as_deref()),
+      MAX_CONTENT_CHUNK_BYTES,
+    )
+  }
+
+  #[tool(description = "Fetch just the diff_refs (base_sha, head_sha, start_sha) of a merge request, ready to use in a create_merge_request_discussion position")]
+  pub async fn get_merge_request_diff_refs(
+    &self,
+    Parameters(req): Parameters<GetMergeRequestDiffRefsRequest>,
+  ) -> Result<CallToolResult, McpError>{
+    let MergeRequestLocator { project, merge_request_iid } = req.locator;
+    let refs = self.state.merge_request_diff_refs(&project, merge_request_iid).await?;
+
+    json_result(project_value(refs, req.projection.fields.as_deref()))
+  }
+
+  #[tool(description = "Create many line-level discussions on one merge request in a single call. Each item has body, position (same shape as create_merge_request_discussion), and optional resolve. Items are posted concurrently; the result lists each item's outcome, and one failure does not stop the others.")]
+  pub async fn create_merge_request_discussions_batch(
+    &self,
+    Parameters(mut req): Parameters<CreateMergeRequestDiscussionsBatchRequest>,
+    meta: Meta,
+    peer: Peer<RoleServer>,
+  ) -> Result<CallToolResult, McpError>{
+    if req.discussions.is_empty() || req.discussions.len() > MAX_BATCH_DISCUSSIONS {
+      return Err(McpError::invalid_params(
+        format!("discussions must contain between 1 and {} items", MAX_BATCH_DISCUSSIONS),
+        None,
+      ));
+    }
+    for discussion in &mut req.discussions {
+      apply_severity(&mut discussion.body, discussion.severity.as_deref(), &self.config.review)?;
+    }
+    let MergeRequestLocator { project, merge_request_iid } = req.locator;
+    let gitlab = self.gitlab(&req.write);
+    let progress = Progress::new(&meta, peer);
+    let count = req.discussions.len();
+
+    let payloads: Vec<Result<Value, McpError>> = req.discussions.iter().map(BatchDiscussion::payload).collect();
+    let results: Vec<Result<Value, McpError>> = stream::iter(payloads)
+      .map(|payload| {
+        let gitlab = gitlab.clone();
+        let project = project.clone();
+        async move {
+          gitlab.create_merge_request_discussion(&project, merge_request_iid, payload?).await
+        }
+      })
+      .buffered(BATCH_CONCURRENCY)
+      .enumerate()
+      .then(|(index, result)| {
+        let progress = progress.clone();
+        async move {
+          if let Some(progress) = progress {
+            progress.report(index + 1, Some(count), format!("discussion {} of {}", index + 1, count)).await;
+          }
+          result
+        }
+      })
+      .collect()
+      .await;
+
+    let merge_request = if !gitlab.is_dry_run() && results.iter().any(Result::is_ok) {
+      self.state.gitlab.get_merge_request(&project, merge_request_iid).await.ok()
+    } else {
+      None
+    };
+    let items: Vec<Value> = results
+      .into_iter()
+      .enumerate()
+      .map(|(index, result)| {
+        let result = match &merge_request {
+          Some(merge_request) => result.map(|created| with_note_web_url(created, merge_request)),
+          None => result,
+        };
+        batch_item_result(index, result)
+      })
+      .collect();
+
+    json_result(Value::Array(items))
+  }
+
+  #[tool(description = "Reply to a merge request discussion, quoting the referenced diff lines of a file as a code block above the reply body. Set quote=false for a plain reply.")]
+  pub async fn quote_reply(
+    &self,
+    Parameters(req): Parameters<QuoteReplyRequest>,
+  ) -> Result<CallToolResult, McpError>{
+    let MergeRequestLocator { project, merge_request_iid } = &req.locator;
+    let end_line = req.end_line.unwrap_or(req.line).max(req.line);
+    let body = if req.quote.unwrap_or(true) {
+      let changes = self
+        .state
+        .merge_request_changes(project, *merge_request_iid)
+        .await?;
+      quote_reply_body(&changes, &req.file_path, req.side, req.line, end_line, &req.body)?
+    } else {
+      req.body.clone()
+    };
+
+    let value = self
+      .gitlab(&req.write)
+      .create_discussion_note(project, *merge_request_iid, &req.discussion_id, serde_json::json!({ "body": body }))
+      .await?;
+    let value = self.with_note_web_url(project, *merge_request_iid, value).await;
+
+    json_result(value)
+  }
+
+  #[tool(description = "Merge a merge request. Optional: merge_commit_message, squash, squash_commit_message, should_remove_source_branch, sha (guard against new pushes), and require_resolved to refuse while review threads are open.")]
+  pub async fn merge_merge_request(
+    &self,
+    Parameters(req): Parameters<MergeMergeRequestRequest>,
+    cancel: CancellationToken,
+  ) -> Result<CallToolResult, McpError>{
+    let payload = merge_payload(&req);
+    let MergeRequestLocator { project, merge_request_iid } = req.locator;
+
+    if req.require_resolved.unwrap_or(false) {
+      let merge_request = self
+        .state
+        .gitlab
+        .get_merge_request(&project, merge_request_iid)
+        .await?;
+      if merge_request.get("blocking_discussions_resolved").and_then(Value::as_bool) == Some(false) {
+        let discussions = self
+          .state
+          .gitlab
+          .with_cancellation(cancel.clone())
+          .list_merge_request_discussions(&project, merge_request_iid)
+          .await?;
+        let unresolved = unresolved_discussion_ids(&discussions.items);
+        return Err(McpError::invalid_request(
+          format!(
+            "Merge request has {} unresolved discussion(s); resolve them before merging",
+            unresolved.len()
+          ),
+          Some(serde_json::json!({ "unresolved_discussions": unresolved })),
+        ));
+      }
+    }
+
+    // The resolved-discussions gate can take a while on a busy merge request;
+    // never merge for a caller who has already cancelled.
+    if cancel.is_cancelled() {
+      return Err(McpError::invalid_request("Merge cancelled by the client", None));
+    }
+
+    let value = self
+      .gitlab(&req.write)
+      .merge_merge_request(&project, merge_request_iid, payload)
+      .await?;
+
+    json_result(value)
+  }
+
+  #[tool(description = "Read a file from the repository. Text files are returned decoded; ref defaults to the project's default branch.")]
+  pub async fn get_repository_file(
+    &self,
+    Parameters(req): Parameters<GetRepositoryFileRequest>,
+  ) -> Result<CallToolResult, McpError>{
+    let ProjectLocator { project } = req.locator;
+    let git_ref = self.resolve_ref(&project, req.git_ref.as_deref()).await?;
+    let value = self
+      .state
+      .gitlab
+      .get_repository_file(&project, &req.file_path, &git_ref)
+      .await?;
+
+    json_result_chunked(
+      project_value(decode_file_content(value), req.projection.fields.as_deref()),
+      MAX_CONTENT_CHUNK_BYTES,
+    )
+  }
+
+  #[tool(description = "Compare two refs (commits and diffs between from and to). from defaults to the project's default branch.")]
+  pub async fn compare_refs(
+    &self,
+    Parameters(req): Parameters<CompareRefsRequest>,
+  ) -> Result<CallToolResult, McpError>{
+    let ProjectLocator { project } = req.locator;
+    let from = self.resolve_ref(&project, req.from.as_deref()).await?;
+    let value = self
+      .state
+      .gitlab
+      .compare_refs(&project, &from, &req.to, req.straight)
+      .await?;
+
+    json_result_chunked(
+      project_value(value, req.projection.fields.as_deref()),
+      MAX_CONTENT_CHUNK_BYTES,
+    )
+  }
+
+  #[tool(description = "Fetch a merge request's changes as a single plain-text unified diff")]
+  pub async fn get_merge_request_raw_diff(
+    &self,
+    Parameters(req): Parameters<GetMergeRequestRawDiffRequest>,
+  ) -> Result<CallToolResult, McpError>{
+    let MergeRequestLocator { project, merge_request_iid } = req.locator;
+    let diff = self
+      .state
+      .gitlab
+      .get_merge_request_raw_diffs(&project, merge_request_iid)
+      .await?;
+
+    Ok(text_result_chunked(&diff, MAX_CONTENT_CHUNK_BYTES))
+  }
+
+  #[tool(description = "Fetch a CI job's log as plain text")]
+  pub async fn get_job_log(
+    &self,
+    Parameters(req): Parameters<GetJobLogRequest>,
+  ) -> Result<CallToolResult, McpError>{
+    let ProjectLocator { project } = req.locator;
+    let log = self
+      .state
+      .gitlab
+      .get_job_trace(&project, req.job_id)
+      .await?;
+
+    Ok(text_result_chunked(&log, MAX_CONTENT_CHUNK_BYTES))
+  }
+
+  #[tool(description = "Fetch one page of a merge request's file diffs from the paginated /diffs endpoint (GitLab 15.7+). Use page/per_page and follow next_page.")]
+  pub async fn get_merge_request_diffs(
+    &self,
+    Parameters(req): Parameters<GetMergeRequestDiffsRequest>,
+  ) -> Result<CallToolResult, McpError>{
+    let MergeRequestLocator { project, merge_request_iid } = req.locator;
+    let page = self
+      .state
+      .gitlab
+      .get_merge_request_diffs(&project, merge_request_iid, req.pagination.page(), req.pagination.per_page())
+      .await?;
+
+    json_result_chunked(
+      project_page(page, req.projection.fields.as_deref()),
+      MAX_CONTENT_CHUNK_BYTES,
+    )
+  }
+
+  #[tool(description = "Summarize a merge request's size: added and removed line counts per file, plus totals, without the diff text")]
+  pub async fn get_merge_request_diff_stats(
+    &self,
+    Parameters(req): Parameters<GetMergeRequestDiffStatsRequest>,
+  ) -> Result<CallToolResult, McpError>{
+    let MergeRequestLocator { project, merge_request_iid } = req.locator;
+    let changes = self
+      .state
+      .merge_request_changes(&project, merge_request_iid)
+      .await?;
+
+    json_result(project_value(diff_stats(&changes), req.projection.fields.as_deref()))
+  }
+
+  #[tool(description = "Close (abandon) an open merge request without merging it. Returns the merge request's new state.")]
+  pub async fn close_merge_request(
+    &self,
+    Parameters(req): Parameters<MergeRequestStateRequest>,
+  ) -> Result<CallToolResult, McpError>{
+    self.change_merge_request_state(req, StateEvent::Close).await
+  }
+
+  #[tool(description = "Reopen a closed merge request. Returns the merge request's new state.")]
+  pub async fn reopen_merge_request(
+    &self,
+    Parameters(req): Parameters<MergeRequestStateRequest>,
+  ) -> Result<CallToolResult, McpError>{
+    self.change_merge_request_state(req, StateEvent::Reopen).await
+  }
+
+  #[tool(description = "Mark a merge request as draft (draft=true) or ready (draft=false) by adding or removing the 'Draft: ' title prefix. Idempotent. Returns the title and draft flag.")]
+  pub async fn set_merge_request_draft(
+    &self,
+    Parameters(req): Parameters<SetMergeRequestDraftRequest>,
   ) -> Result<CallToolResult, McpError>{
     let MergeRequestLocator { project, merge_request_iid } = req.locator;
+    let merge_request = self.state.gitlab.get_merge_request(&project, merge_request_iid).await?;
+    let title = merge_request.get("title").and_then(Value::as_str).unwrap_or_default();
+    let new_title = draft_title(title, req.draft);
+    if new_title == title {
+      return json_result(draft_result(&merge_request));
+    }
+
+    let value = self
+      .gitlab(&req.write)
+      .update_merge_request(&project, merge_request_iid, serde_json::json!({ "title": new_title }))
+      .await?;
+    if is_dry_run_response(&value) {
+      return json_result(value);
+    }
+
+    json_result(draft_result(&value))
+  }
+
+  #[tool(description = "Subscribe the token user to notifications for a merge request, e.g. to hear about replies to a discussion. Already subscribed is not an error. Returns the subscription state.")]
+  pub async fn subscribe_merge_request(
+    &self,
+    Parameters(req): Parameters<MergeRequestStateRequest>,
+  ) -> Result<CallToolResult, McpError>{
+    let MergeRequestLocator { project, merge_request_iid } = &req.locator;
+    let updated = self
+      .gitlab(&req.write)
+      .subscribe_merge_request(project, *merge_request_iid)
+      .await?;
+    self.subscription_result(&req, updated).await
+  }
+
+  #[tool(description = "Unsubscribe the token user from notifications for a merge request. Not being subscribed is not an error. Returns the subscription state.")]
+  pub async fn unsubscribe_merge_request(
+    &self,
+    Parameters(req): Parameters<MergeRequestStateRequest>,
+  ) -> Result<CallToolResult, McpError>{
+    let MergeRequestLocator { project, merge_request_iid } = &req.locator;
+    let updated = self
+      .gitlab(&req.write)
+      .unsubscribe_merge_request(project, *merge_request_iid)
+      .await?;
+    self.subscription_result(&req, updated).await
+  }
+
+  #[tool(description = "Set a merge request's time estimate, e.g. duration=\"3h30m\". Returns the time-tracking stats.")]
+  pub async fn set_merge_request_time_estimate(
+    &self,
+    Parameters(req): Parameters<SetTimeEstimateRequest>,
+  ) -> Result<CallToolResult, McpError>{
+    let duration = validate_duration(&req.duration, false)?;
+    let MergeRequestLocator { project, merge_request_iid } = &req.locator;
+    let value = self
+      .gitlab(&req.write)
+      .set_merge_request_time_estimate(project, *merge_request_iid, serde_json::json!({ "duration": duration }))
+      .await?;
+
+    json_result(value)
+  }
+
+  #[tool(description = "Log time spent on a merge request, e.g. duration=\"45m\" (prefix '-' to subtract), with an optional summary. Returns the time-tracking stats.")]
+  pub async fn add_merge_request_spent_time(
+    &self,
+    Parameters(req): Parameters<AddSpentTimeRequest>,
+  ) -> Result<CallToolResult, McpError>{
+    let duration = validate_duration(&req.duration, true)?;
+    let mut payload = serde_json::json!({ "duration": duration });
+    if let Some(summary) = req.summary.as_deref().filter(|summary| !summary.trim().is_empty()) {
+      payload["summary"] = Value::String(summary.to_string());
+    }
+    let MergeRequestLocator { project, merge_request_iid } = &req.locator;
     let value = self
+      .gitlab(&req.write)
+      .add_merge_request_spent_time(project, *merge_request_iid, payload)
+      .await?;
+
+    json_result(value)
+  }
+
+  #[tool(description = "List a merge request's history of one event_type: label (labels added/removed), state (closed, reopened, merged), or milestone (milestone changes). Oldest first.")]
+  pub async fn get_merge_request_events(
+    &self,
+    Parameters(req): Parameters<GetMergeRequestEventsRequest>,
+  ) -> Result<CallToolResult, McpError>{
+    let MergeRequestLocator { project, merge_request_iid } = req.locator;
+    let events = self
       .state
       .gitlab
-      .get_merge_request(&project, merge_request_iid)
+      .list_merge_request_resource_events(&project, merge_request_iid, req.event_type.resource())
+      .await?;
+
+    json_result_chunked(
+      project_value(events.items, req.projection.fields.as_deref()),
+      MAX_CONTENT_CHUNK_BYTES,
+    )
+  }
+
+  #[tool(description = "Move an issue to another project (to_project: ID or full path). Returns the new issue in the destination project.")]
+  pub async fn move_issue(
+    &self,
+    Parameters(req): Parameters<MoveIssueRequest>,
+  ) -> Result<CallToolResult, McpError>{
+    let to_project = req.to_project.trim();
+    if to_project.is_empty() {
+      return Err(McpError::invalid_params("to_project must not be empty", None));
+    }
+    let destination = self.state.project(to_project).await?;
+    let to_project_id = destination.get("id").and_then(Value::as_u64).ok_or_else(|| {
+      McpError::internal_error("GitLab project response has no id", Some(destination.clone()))
+    })?;
+
+    let ProjectLocator { project } = &req.locator;
+    let value = self
+      .gitlab(&req.write)
+      .move_issue(project, req.issue_iid, to_project_id)
       .await?;
 
     json_result(value)
   }
 
-  #[tool(description = "Fetch the diff changes for a GitLab merge request (file list and hunks)")]
-  pub async fn get_merge_request_changes(
+  #[tool(description = "Link two issues: source relates_to, blocks, or is_blocked_by the target. The target may be in another project. Returns the link with both issues.")]
+  pub async fn create_issue_link(
     &self,
-    Parameters(req): Parameters<GetMergeRequestChangesRequest>,
+    Parameters(req): Parameters<CreateIssueLinkRequest>,
   ) -> Result<CallToolResult, McpError>{
-    let MergeRequestLocator { project, merge_request_iid } = req.locator;
+    let ProjectLocator { project } = &req.locator;
+    let target_project = req
+      .target_project
+      .as_deref()
+      .map(str::trim)
+      .filter(|target| !target.is_empty())
+      .unwrap_or(project);
+    let target = self.state.project(target_project).await?;
+    let target_project_id = target.get("id").and_then(Value::as_u64).ok_or_else(|| {
+      McpError::internal_error("GitLab project response has no id", Some(target.clone()))
+    })?;
+    let payload = serde_json::json!({
+      "target_project_id": target_project_id,
+      "target_issue_iid": req.target_issue_iid,
+      "link_type": req.link_type.as_str(),
+    });
+
+    let value = self
+      .gitlab(&req.write)
+      .create_issue_link(project, req.issue_iid, payload)
+      .await?;
+
+    json_result(value)
+  }
+
+  #[tool(description = "Remove a link between two issues by its issue_link_id. Returns the removed link.")]
+  pub async fn delete_issue_link(
+    &self,
+    Parameters(req): Parameters<DeleteIssueLinkRequest>,
+  ) -> Result<CallToolResult, McpError>{
+    let ProjectLocator { project } = &req.locator;
     let value = self
+      .gitlab(&req.write)
+      .delete_issue_link(project, req.issue_iid, req.issue_link_id)
+      .await?;
+
+    json_result(value)
+  }
+
+  #[tool(description = "Summarize a project's review backlog in one call: total open merge requests, how many have a failing latest pipeline, how many are approved and mergeable, and how many are stale (no update in stale_days, default 7), with the IIDs in each group.")]
+  pub async fn get_review_backlog(
+    &self,
+    Parameters(req): Parameters<GetReviewBacklogRequest>,
+  ) -> Result<CallToolResult, McpError>{
+    let ProjectLocator { project } = &req.locator;
+    let walk = PageWalk {
+      keyset: false,
+      max_items: req.max_items.unwrap_or(DEFAULT_MAX_ITEMS),
+    };
+    let query = [("state", "opened".to_string()), ("per_page", "100".to_string())];
+    let page = self
       .state
       .gitlab
-      .get_merge_request_changes(&project, merge_request_iid)
+      .list_merge_requests(project, &query, Some(walk))
       .await?;
+    let merge_requests = match page.items {
+      Value::Array(items) => items,
+      _ => Vec::new(),
+    };
 
-    json_result(value)
+    let pipelines = if req.check_pipelines.unwrap_or(true) {
+      let iids: Vec<u64> = merge_requests
+        .iter()
+        .map(|merge_request| merge_request.get("iid").and_then(Value::as_u64).unwrap_or_default())
+        .collect();
+      let lookups: Vec<Option<Value>> = stream::iter(iids)
+        .map(|iid| {
+          let gitlab = self.state.gitlab.clone();
+          let project = project.clone();
+          async move {
+            match gitlab.get_latest_merge_request_pipeline(&project, iid).await {
+              Ok(pipeline) => pipeline,
+              Err(err) => {
+                tracing::warn!("Could not look up pipeline for merge request !{}: {}", iid, err.message);
+                None
+              }
+            }
+          }
+        })
+        .buffered(BATCH_CONCURRENCY)
+        .collect()
+        .await;
+      Some(lookups)
+    } else {
+      None
+    };
+
+    let value = review_backlog(
+      &merge_requests,
+      pipelines.as_deref(),
+      req.stale_days.unwrap_or(7),
+      chrono::Utc::now(),
+    );
+
+    json_result(project_value(value, req.projection.fields.as_deref()))
   }
 
-  #[tool(description = "Fetch merge request versions (base/head/start commit SHAs for discussions)")]
-  pub async fn get_merge_request_versions(
+  #[tool(description = "List every discussion thread on a merge request with its notes. Set unresolved_only to keep just the threads that still have an unresolved note.")]
+  pub async fn list_merge_request_discussions(
     &self,
-    Parameters(req): Parameters<GetMergeRequestVersionsRequest>,
+    Parameters(req): Parameters<ListMergeRequestDiscussionsRequest>,
+    meta: Meta,
+    peer: Peer<RoleServer>,
+    cancel: CancellationToken,
   ) -> Result<CallToolResult, McpError>{
     let MergeRequestLocator { project, merge_request_iid } = req.locator;
-    let value = self
+    let mut page = self
       .state
       .gitlab
-      .get_merge_request_versions(&project, merge_request_iid)
+      .with_progress(Progress::new(&meta, peer))
+      .with_cancellation(cancel)
+      .list_merge_request_discussions(&project, merge_request_iid)
       .await?;
+    if req.unresolved_only.unwrap_or(false) {
+      page.items = unresolved_discussions(page.items);
+    }
 
-    json_result(value)
+    json_result_chunked(
+      project_page(page, req.projection.fields.as_deref()),
+      MAX_CONTENT_CHUNK_BYTES,
+    )
   }
 
-  #[tool(description = "Create a line-level discussion on a GitLab merge request. The position field requires: base_sha, head_sha, start_sha (from get_merge_request_versions), new_path, old_path, and line numbers (new_line for additions, old_line for deletions). Position can be a JSON object or string. The position_type defaults to 'text'.")]
-  pub async fn create_merge_request_discussion(
+  #[tool(description = "Fetch a merge request's approval rules with their state: for each rule, whether it is approved, approvals_required, eligible_approvers, and approved_by. Use it to tell whether a given user's approval would satisfy a rule. Requires GitLab Premium.")]
+  pub async fn get_merge_request_approval_state(
     &self,
-    Parameters(req): Parameters<CreateMergeRequestDiscussionRequest>,
+    Parameters(req): Parameters<GetMergeRequestApprovalStateRequest>,
   ) -> Result<CallToolResult, McpError>{
-    let payload = discussion_payload(&req)?;
     let MergeRequestLocator { project, merge_request_iid } = req.locator;
     let value = self
       .state
       .gitlab
-      .create_merge_request_discussion(&project, merge_request_iid, payload)
+      .get_merge_request_approval_state(&project, merge_request_iid)
       .await?;
 
-    json_result(value)
+    json_result(project_value(value, req.projection.fields.as_deref()))
   }
 
-  #[tool(description = "Create a general note on a GitLab merge request (top-level discussion comment)")]
-  pub async fn create_merge_request_note(
+  #[tool(description = "List jobs across all of a project's pipelines, newest first. Filter by scope (job statuses such as failed or running) and, optionally, name_contains. Useful for finding recent failing jobs; fetch a log with get_job_log.")]
+  pub async fn list_project_jobs(
     &self,
-    Parameters(req): Parameters<CreateMergeRequestNoteRequest>,
+    Parameters(req): Parameters<ListProjectJobsRequest>,
+  ) -> Result<CallToolResult, McpError>{
+    let scopes: Vec<&str> = req.scope.iter().map(JobScope::as_str).collect();
+    let mut page = self
+      .state
+      .gitlab
+      .list_project_jobs(
+        &req.locator.project,
+        &scopes,
+        req.pagination.page(),
+        req.pagination.per_page(),
+      )
+      .await?;
+    if let Some(needle) = &req.name_contains {
+      page.items = filter_jobs_by_name(page.items, needle);
+    }
+
+    json_result(project_page(page, req.projection.fields.as_deref()))
+  }
+
+  #[tool(description = "List only the failing test cases of a pipeline's test report, sorted by suite: suite, name, classname, and a truncated stack_trace. status is passing, with an empty failures array, when nothing failed. The smallest useful input for fixing broken tests.")]
+  pub async fn get_pipeline_failures(
+    &self,
+    Parameters(req): Parameters<GetPipelineFailuresRequest>,
+  ) -> Result<CallToolResult, McpError>{
+    let report = self
+      .state
+      .gitlab
+      .get_pipeline_test_report(&req.locator.project, req.pipeline_id)
+      .await?;
+
+    let failures = pipeline_failures(&report, req.max_stack_trace_chars());
+
+    json_result_chunked(project_value(failures, req.projection.fields.as_deref()), MAX_CONTENT_CHUNK_BYTES)
+  }
+
+  #[tool(description = "Summarize a merge request in one call: title, author, branches, merge status, files changed, lines added/removed, commit count, unresolved threads, and latest pipeline status. Parts that cannot be fetched, including the merge request metadata itself, are null; the call only fails when nothing could be fetched.")]
+  pub async fn summarize_merge_request(
+    &self,
+    Parameters(req): Parameters<SummarizeMergeRequestRequest>,
   ) -> Result<CallToolResult, McpError>{
-    let payload = note_payload(&req);
     let MergeRequestLocator { project, merge_request_iid } = req.locator;
-    let value = self
+    let gitlab = &self.state.gitlab;
+    let (merge_request, changes, commits, discussions, pipeline) = tokio::join!(
+      gitlab.get_merge_request(&project, merge_request_iid),
+      self.state.merge_request_changes(&project, merge_request_iid),
+      gitlab.list_merge_request_commits(&project, merge_request_iid),
+      gitlab.list_merge_request_discussions(&project, merge_request_iid),
+      gitlab.get_latest_merge_request_pipeline(&project, merge_request_iid),
+    );
+    let partial = |part: &str, err: McpError| {
+      tracing::warn!("Merge request summary is missing {}: {}", part, err.message);
+    };
+    let merge_request = match merge_request {
+      Ok(merge_request) => merge_request,
+      // Nothing could be fetched, e.g. the merge request does not exist.
+      Err(err) if changes.is_err() && commits.is_err() && discussions.is_err() && pipeline.is_err() => {
+        return Err(err);
+      }
+      Err(err) => {
+        partial("metadata", err);
+        serde_json::json!({ "iid": merge_request_iid })
+      }
+    };
+    let stats = changes.map(|changes| diff_stats(&changes)).map_err(|err| partial("diff stats", err)).ok();
+    let commits = commits.map_err(|err| partial("commits", err)).ok();
+    let discussions = discussions.map_err(|err| partial("discussions", err)).ok();
+    let pipeline = pipeline.map_err(|err| partial("pipeline", err)).ok();
+
+    let value = merge_request_summary(
+      &merge_request,
+      stats.as_ref(),
+      commits.as_ref().map(|page| &page.items),
+      discussions.as_ref().map(|page| &page.items),
+      pipeline.as_ref().map(Option::as_ref),
+    );
+
+    json_result(project_value(value, req.projection.fields.as_deref()))
+  }
+
+  #[tool(description = "Check a merge request for merge conflicts before acting on it. Rechecks mergeability and returns has_conflicts, merge_status, detailed_merge_status, merge_error (GitLab's reason the last merge or rebase failed), and, when conflicting, possibly_conflicting_files: the files changed on both the source and target branches since the merge base, which include every conflict but may include files that merge cleanly.")]
+  pub async fn get_merge_request_conflicts(
+    &self,
+    Parameters(req): Parameters<GetMergeRequestConflictsRequest>,
+  ) -> Result<CallToolResult, McpError>{
+    let MergeRequestLocator { project, merge_request_iid } = req.locator;
+    let merge_request = self
       .state
       .gitlab
-      .create_merge_request_note(&project, merge_request_iid, payload)
+      .get_merge_request_rechecked(&project, merge_request_iid)
       .await?;
+    let has_conflicts = merge_request["has_conflicts"].as_bool().unwrap_or(false);
+    let possibly_conflicting_files = match (has_conflicts, merge_request["diff_refs"]["base_sha"].as_str()) {
+      (false, _) => Some(Vec::new()),
+      (true, None) => None,
+      (true, Some(base_sha)) => {
+        let target_branch = merge_request["target_branch"].as_str().unwrap_or_default();
+        let (changes, target_compare) = tokio::join!(
+          self.state.merge_request_changes(&project, merge_request_iid),
+          self.state.gitlab.compare_refs(&project, base_sha, target_branch, None),
+        );
+        match (changes, target_compare) {
+          (Ok(changes), Ok(target_compare)) => Some(overlapping_files(&changes, &target_compare)),
+          (Err(err), _) | (_, Err(err)) => {
+            tracing::warn!("Could not list possibly conflicting files: {}", err.message);
+            None
+          }
+        }
+      }
+    };
 
-    json_result(value)
+    let value = serde_json::json!({
+      "has_conflicts": has_conflicts,
+      "merge_status": merge_request["merge_status"],
+      "detailed_merge_status": merge_request["detailed_merge_status"],
+      "merge_error": merge_request["merge_error"],
+      "possibly_conflicting_files": possibly_conflicting_files,
+    });
+
+    json_result(project_value(value, req.projection.fields.as_deref()))
+  }
+
+  #[tool(description = "List the open merge requests, across all projects, where a user is a requested reviewer. Defaults to the user the token belongs to; pass username to see someone else's queue.")]
+  pub async fn list_my_review_requests(
+    &self,
+    Parameters(req): Parameters<ListMyReviewRequestsRequest>,
+  ) -> Result<CallToolResult, McpError>{
+    let username = match &req.username {
+      Some(username) => username.trim().trim_start_matches('@').to_string(),
+      None => self.state.current_username().await?,
+    };
+    let mut page = self
+      .state
+      .gitlab
+      .list_review_requests(&username, req.pagination.page(), req.pagination.per_page())
+      .await?;
+    if req.compact.unwrap_or(false) {
+      page.items = compact_merge_requests(page.items);
+    }
+
+    json_result(project_page(page, req.projection.fields.as_deref()))
+  }
+
+  #[tool(description = "List the token user's GitLab to-do items (the \"what needs my attention\" inbox): review requests, mentions, assignments, and failed pipelines. Filter by state (pending or done) and type.")]
+  pub async fn list_todos(
+    &self,
+    Parameters(req): Parameters<ListTodosRequest>,
+  ) -> Result<CallToolResult, McpError>{
+    let page = self.state.gitlab.list_todos(&req.query()).await?;
+
+    json_result(project_page(page, req.projection.fields.as_deref()))
+  }
+
+  #[tool(description = "Mark one of the token user's to-do items as done. Returns the updated to-do.")]
+  pub async fn mark_todo_done(
+    &self,
+    Parameters(req): Parameters<MarkTodoDoneRequest>,
+  ) -> Result<CallToolResult, McpError>{
+    let todo = self
+      .gitlab(&req.write)
+      .mark_todo_done(req.todo_id)
+      .await?;
+
+    json_result(todo)
+  }
+}
+
+#[cfg(feature = "database")]
+use crate::tools::gitlab::GetAuditLogRequest;
+
+#[tool_router(router = rest_tool_router)]
+impl Server {
+  #[tool(description = "Escape hatch: send a request to any GitLab REST endpoint. method is GET, POST, PUT, or DELETE; path is relative to /api/v4; query and body are optional. Prefer the typed tools when one exists. Returns the raw response.")]
+  pub async fn gitlab_rest(
+    &self,
+    Parameters(req): Parameters<GitLabRestRequest>,
+  ) -> Result<CallToolResult, McpError>{
+    let query: Vec<(String, String)> = req.query.unwrap_or_default().into_iter().collect();
+    let value = self
+      .gitlab(&req.write)
+      .rest(req.method.as_method(), &req.path, &query, req.body)
+      .await?;
+
+    json_result_chunked(value, MAX_CONTENT_CHUNK_BYTES)
+  }
+}
+
+#[cfg(feature = "database")]
+#[tool_router(router = audit_tool_router)]
+impl Server {
+  #[tool(description = "List the most recent write tool calls recorded in the local audit log, newest first")]
+  pub async fn get_audit_log(
+    &self,
+    Parameters(req): Parameters<GetAuditLogRequest>,
+  ) -> Result<CallToolResult, McpError>{
+    let Some(db) = &self.state.db else {
+      return Err(McpError::invalid_request("The audit log needs a configured database", None));
+    };
+    let entries = audit::recent(db, req.limit()).await.map_err(|err| {
+      McpError::internal_error("Failed to read the audit log", Some(Value::String(err.to_string())))
+    })?;
+
+    json_result(project_value(Value::Array(entries), req.projection.fields.as_deref()))
   }
 }
 
@@ -125,12 +1594,72 @@ impl Server {
     tracing::info!("Loading server state and tools...");
     
     let state = ServerState::new(&config).await?;
+    if config.server.dry_run {
+      tracing::warn!("Dry-run mode is active: GitLab write requests will be previewed, not sent");
+    }
+
+    let mut tool_router = Self::tool_router();
+    #[cfg(feature = "database")]
+    if state.db.is_some() {
+      tool_router += Self::audit_tool_router();
+    }
+    if config.server.enable_rest_passthrough {
+      tool_router += Self::rest_tool_router();
+      tracing::warn!("REST passthrough is enabled: gitlab_rest can call any GitLab endpoint");
+    }
+    if config.server.read_only {
+      for name in WRITE_TOOLS {
+        tool_router.remove_route(name);
+      }
+      tracing::info!("Read-only mode is active: write tools are not registered");
+    }
+    if let Some(enabled) = &config.server.enabled_tools {
+      let registered: Vec<String> = tool_router.list_all().into_iter().map(|tool| tool.name.into_owned()).collect();
+      for name in enabled {
+        if !registered.contains(name) {
+          tracing::warn!("enabled_tools lists unknown or disabled tool '{}'", name);
+        }
+      }
+      for name in registered.iter().filter(|name| !enabled.contains(name)) {
+        tool_router.remove_route(name);
+      }
+    }
+    let mut enabled: Vec<String> = tool_router.list_all().into_iter().map(|tool| tool.name.into_owned()).collect();
+    enabled.sort();
+    tracing::info!("Enabled tools ({}): {}", enabled.len(), enabled.join(", "));
+    if config.server.check_token_scopes {
+      let writes_enabled = enabled.iter().any(|name| WRITE_TOOLS.contains(&name.as_str()));
+      Self::check_token_scopes(&state.gitlab, writes_enabled).await;
+    }
     
     tracing::info!("Server initialization complete");
-    Ok(Self { config, state, tool_router: Self::tool_router(), })
+    Ok(Self { config, state, tool_router })
+  }
+
+  /// Logs the token's scopes and expiry, with a warning for each problem
+  /// `token_warnings` finds. Never fails startup: tokens that cannot read
+  /// themselves only get a warning that the check was skipped.
+  async fn check_token_scopes(gitlab: &GitLabClient, writes_enabled: bool) {
+    match gitlab.get_token_self().await {
+      Ok(token) => {
+        tracing::info!(
+          "GitLab token '{}' scopes: {}, expires_at: {}",
+          token["name"].as_str().unwrap_or("unknown"),
+          token["scopes"],
+          token["expires_at"],
+        );
+        for warning in token_warnings(&token, writes_enabled, chrono::Utc::now().date_naive()) {
+          tracing::warn!("{}", warning);
+        }
+      }
+      Err(err) => tracing::warn!("Could not check GitLab token scopes: {}", err.message),
+    }
   }
 
   pub async fn run(self) -> anyhow::Result<()> {
+    let state = self.state.clone();
+    let grace = self.config.server.shutdown_grace();
+
     match &self.config.server.transport {
       config::TransportType::Stdio => {
         tracing::info!("MCP Server ready!");
@@ -141,16 +1670,21 @@ impl Server {
 
         // Set up graceful shutdown
         let shutdown = tokio::spawn(async move {
-          tokio::signal::ctrl_c().await.ok();
+          shutdown_signal().await;
           tracing::info!("Shutdown signal received");
         });
 
+        // Keep the service alive while draining so in-flight calls can finish
+        let waiting = service.waiting();
+        tokio::pin!(waiting);
+
         tokio::select! {
-          result = service.waiting() => {
+          result = &mut waiting => {
             tracing::info!("Server stopped: {:?}", result);
           }
           _ = shutdown => {
             tracing::info!("Shutting down gracefully");
+            Self::drain(&state, grace).await;
           }
         }
       }
@@ -182,18 +1716,25 @@ impl Server {
           }));
         
         let listener = tokio::net::TcpListener::bind(addr).await?;
-        let server = axum::serve(listener, app);
+
+        // Stop accepting new connections once the shutdown signal fires
+        let (stop_accepting, stopped) = tokio::sync::oneshot::channel::<()>();
+        let server = axum::serve(listener, app)
+          .with_graceful_shutdown(async move {
+            stopped.await.ok();
+          });
         
         // Set up graceful shutdown using the same pattern as STDIO
         let shutdown = tokio::spawn(async move {
-          if let Err(e) = tokio::signal::ctrl_c().await {
-            tracing::error!("Failed to listen for shutdown signal: {}", e);
-          }
+          shutdown_signal().await;
           tracing::info!("Shutdown signal received");
         });
 
+        let server = server.into_future();
+        tokio::pin!(server);
+
         tokio::select! {
-          result = server => {
+          result = &mut server => {
             match result {
               Ok(_) => tracing::info!("HTTP server stopped normally"),
               Err(e) => tracing::error!("HTTP server stopped with error: {}", e),
@@ -201,6 +1742,8 @@ impl Server {
           }
           _ = shutdown => {
             tracing::info!("Shutting down gracefully");
+            stop_accepting.send(()).ok();
+            Self::drain(&state, grace).await;
           }
         }
       }
@@ -208,10 +1751,258 @@ impl Server {
 
     Ok(())
   }
+
+  /// GitLab client for a write tool, honoring a per-request `dry_run` and
+  /// `sudo`. A request can turn dry-run on but never off when the server
+  /// enables it.
+  fn gitlab(&self, write: &WriteOptions) -> GitLabClient {
+    let gitlab = if write.dry_run.unwrap_or(false) && !self.state.gitlab.is_dry_run() {
+      self.state.gitlab.with_dry_run(true)
+    } else {
+      self.state.gitlab.clone()
+    };
+    match write.sudo.as_deref().map(str::trim).filter(|sudo| !sudo.is_empty()) {
+      Some(sudo) => gitlab.with_sudo(sudo),
+      None => gitlab,
+    }
+  }
+
+  /// Settle a write call from the tool's own result: drop the cached merge
+  /// requests it may have changed, then audit it.
+  async fn finish_write(
+    &self,
+    tool: &str,
+    arguments: Option<&JsonObject>,
+    result: &Result<CallToolResult, McpError>,
+  ) {
+    let output = match result {
+      Ok(result) => result.structured_content.clone().unwrap_or(Value::Null),
+      Err(_) => Value::Null,
+    };
+    if result.is_ok() && !is_dry_run_response(&output) && DIFF_CHANGING_TOOLS.contains(&tool) {
+      self.invalidate_written_merge_requests(arguments).await;
+    }
+    self.audit_write(tool, arguments, result, &output).await;
+  }
+
+  /// Drop cached changes and diff_refs after a diff-changing write: just the
+  /// merge request the arguments name, or every entry when they name none
+  /// (a commit onto a branch, or `gitlab_rest`).
+  async fn invalidate_written_merge_requests(&self, arguments: Option<&JsonObject>) {
+    match call_target(arguments) {
+      (Some(project), Some(merge_request_iid)) => {
+        self.state.invalidate_merge_request(&project, merge_request_iid).await
+      }
+      _ => self.state.invalidate_merge_requests(),
+    }
+  }
+
+  /// Record a write tool call in the audit log, and report it to the audit
+  /// webhook when it actually changed GitLab; dry runs and idempotent
+  /// replays did not. `output` is the tool's structured content.
+  async fn audit_write(
+    &self,
+    tool: &str,
+    arguments: Option<&JsonObject>,
+    result: &Result<CallToolResult, McpError>,
+    output: &Value,
+  ) {
+    let status = match result {
+      Ok(result) if is_replayed(result) => "replayed",
+      Ok(result) if result.is_error != Some(true) => {
+        if is_dry_run_response(output) { "dry_run" } else { "ok" }
+      }
+      _ => "error",
+    };
+    let (project, mr_iid) = call_target(arguments);
+    let event = AuditEvent::new(tool, project, mr_iid, output);
+
+    #[cfg(feature = "database")]
+    if let Some(db) = &self.state.db {
+      let payload_hash = audit::payload_hash(arguments);
+      if let Err(err) = audit::record(db, &event, &payload_hash, status).await {
+        tracing::warn!(tool, error = %err, "Failed to write audit log entry");
+      }
+    }
+
+    if status == "ok" {
+      if let Some(webhook) = &self.state.audit_webhook {
+        webhook.send(event);
+      }
+    }
+  }
+
+  /// Close or reopen a merge request after checking it is not already in
+  /// the target state.
+  async fn change_merge_request_state(
+    &self,
+    req: MergeRequestStateRequest,
+    event: StateEvent,
+  ) -> Result<CallToolResult, McpError> {
+    let MergeRequestLocator { project, merge_request_iid } = req.locator;
+    let merge_request = self.state.gitlab.get_merge_request(&project, merge_request_iid).await?;
+    check_state_event(&merge_request, event)?;
+
+    let value = self
+      .gitlab(&req.write)
+      .update_merge_request(&project, merge_request_iid, state_event_payload(event))
+      .await?;
+    if is_dry_run_response(&value) {
+      return json_result(value);
+    }
+
+    json_result(compact_merge_request(&value))
+  }
+
+  /// The result of a (un)subscribe call. GitLab answers 304 without a body
+  /// when nothing changed, so the merge request is looked up in that case.
+  async fn subscription_result(
+    &self,
+    req: &MergeRequestStateRequest,
+    updated: Option<Value>,
+  ) -> Result<CallToolResult, McpError> {
+    let MergeRequestLocator { project, merge_request_iid } = &req.locator;
+    match updated {
+      Some(value) if is_dry_run_response(&value) => json_result(value),
+      Some(merge_request) => json_result(subscription_result(&merge_request, true)),
+      None => {
+        let merge_request = self
+          .gitlab(&WriteOptions { dry_run: None, sudo: req.write.sudo.clone() })
+          .get_merge_request(project, *merge_request_iid)
+          .await?;
+        json_result(subscription_result(&merge_request, false))
+      }
+    }
+  }
+
+  /// The ref a caller asked for, or the project's default branch when they
+  /// left it out.
+  async fn resolve_ref(&self, project: &str, git_ref: Option<&str>) -> Result<String, McpError> {
+    match git_ref.map(str::trim).filter(|git_ref| !git_ref.is_empty()) {
+      Some(git_ref) => Ok(git_ref.to_string()),
+      None => self.state.default_branch(project).await,
+    }
+  }
+
+  /// A copy of this server whose GitLab client counts its requests into
+  /// `counter`, so one tool call's requests can be reported.
+  fn with_request_counter(&self, counter: Arc<AtomicUsize>) -> Self {
+    let mut server = self.clone();
+    server.state.gitlab = server.state.gitlab.with_request_counter(counter);
+    server
+  }
+
+  /// Link a freshly created note or discussion to its anchor on the merge
+  /// request page. The write already happened, so a failed lookup only
+  /// drops the link.
+  async fn with_note_web_url(&self, project: &str, merge_request_iid: u64, created: Value) -> Value {
+    if is_dry_run_response(&created) {
+      return created;
+    }
+    match self.state.gitlab.get_merge_request(project, merge_request_iid).await {
+      Ok(merge_request) => with_note_web_url(created, &merge_request),
+      Err(err) => {
+        tracing::warn!("Could not look up merge request web_url: {}", err.message);
+        created
+      }
+    }
+  }
+
+  async fn drain(state: &ServerState, grace: std::time::Duration) {
+    let active = state.active_calls();
+    if active == 0 {
+      return;
+    }
+    tracing::info!("Waiting up to {:?} for {} in-flight tool call(s)", grace, active);
+    if state.drain(grace).await {
+      tracing::info!("In-flight tool calls finished");
+    } else {
+      tracing::warn!(
+        "Shutdown grace period expired with {} tool call(s) still running",
+        state.active_calls()
+      );
+    }
+  }
 }
 
-#[tool_handler]
 impl ServerHandler for Server {
+  async fn call_tool(
+    &self,
+    request: CallToolRequestParam,
+    context: RequestContext<RoleServer>,
+  ) -> Result<CallToolResult, McpError> {
+    let _active = self.state.begin_call();
+    let tool = request.name.clone();
+    let is_write = is_write_call(&tool, request.arguments.as_ref());
+    let arguments = if is_write { request.arguments.clone() } else { None };
+    let timeout = self.config.server.tool_timeout(
+      context.meta.get("timeout_secs").and_then(Value::as_u64),
+    );
+
+    let call = async {
+      if self.config.server.debug {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let server = self.with_request_counter(requests.clone());
+        let tcc = ToolCallContext::new(&server, request, context);
+        self.tool_router.call(tcc).await.map(|mut result| {
+          let mut meta = result.meta.take().unwrap_or_default();
+          meta.insert("gitlab_requests".to_string(), requests.load(Ordering::Relaxed).into());
+          result.meta = Some(meta);
+          result
+        })
+      } else {
+        let tcc = ToolCallContext::new(self, request, context);
+        self.tool_router.call(tcc).await
+      }
+    };
+    let result = match timeout {
+      Some(timeout) => tokio::time::timeout(timeout, call).await.unwrap_or_else(|_| {
+        Err(McpError::internal_error(
+          "tool call exceeded deadline",
+          Some(serde_json::json!({ "tool": tool, "timeout_secs": timeout.as_secs() })),
+        ))
+      }),
+      None => call.await,
+    };
+
+    // A write has happened by now whatever max_response_bytes makes of its
+    // result, so it is settled from the tool's own result first.
+    if is_write {
+      self.finish_write(&tool, arguments.as_ref(), &result).await;
+    }
+    let result = result.map(|mut result| {
+      if !self.config.server.structured_output {
+        result.structured_content = None;
+      }
+      result
+    });
+    match (result, self.config.server.max_response_bytes) {
+      (Ok(result), Some(max_bytes)) if result_size(&result) > max_bytes => {
+        let size = result_size(&result);
+        tracing::warn!(tool = %tool, size, max_bytes, "Tool result exceeds max_response_bytes");
+        match self.config.server.oversize_response {
+          OversizeResponse::Truncate => Ok(truncate_result(result, max_bytes)),
+          OversizeResponse::Error => Err(McpError::invalid_request(
+            format!(
+              "{} returned {} bytes, over the server's {} byte limit; narrow the request (fields, paths, or a smaller page)",
+              tool, size, max_bytes
+            ),
+            None,
+          )),
+        }
+      }
+      (result, _) => result,
+    }
+  }
+
+  async fn list_tools(
+    &self,
+    _request: Option<PaginatedRequestParam>,
+    _context: RequestContext<RoleServer>,
+  ) -> Result<ListToolsResult, McpError> {
+    Ok(ListToolsResult::with_all_items(self.tool_router.list_all()))
+  }
+
   fn get_info(&self) -> ServerInfo {
     ServerInfo {
       protocol_version: ProtocolVersion::default(),
@@ -225,7 +2016,44 @@ impl ServerHandler for Server {
       capabilities: ServerCapabilities::builder()
         .enable_tools()
         .build(),
-      instructions: Some("GitLab merge request review tools. Set GITLAB_URL (without /api/v4) and GITLAB_TOKEN before launch. Workflow: (1) get_merge_request for metadata and get_merge_request_changes for diff context; (2) get_merge_request_versions and take the first entry's base/head/start commit SHAs; (3) call create_merge_request_discussion with body markdown and a position JSON containing: base_sha, head_sha, start_sha, new_path, old_path, and line numbers (new_line for additions, old_line for deletions). The position_type field defaults to 'text' if not specified. Use create_merge_request_note for top-level MR comments.".to_string()),
+      instructions: Some(
+        self.config.server.instructions.clone().unwrap_or_else(|| DEFAULT_INSTRUCTIONS.to_string()),
+      ),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use serde_json::json;
+
+  fn arguments(value: Value) -> JsonObject {
+    match value {
+      Value::Object(map) => map,
+      _ => unreachable!(),
     }
   }
+
+  #[test]
+  fn call_target_resolves_every_project_form() {
+    let url = arguments(json!({ "project": "https://gitlab.com/group/app/-/merge_requests/4", "body": "x" }));
+    assert_eq!(call_target(Some(&url)), (Some("group/app".to_string()), Some(4)));
+
+    let parts = arguments(json!({ "namespace": "group/sub", "name": "app", "merge_request_iid": 2 }));
+    assert_eq!(call_target(Some(&parts)), (Some("group/sub/app".to_string()), Some(2)));
+
+    let project = arguments(json!({ "project": "group/app", "branch": "main" }));
+    assert_eq!(call_target(Some(&project)), (Some("group/app".to_string()), None));
+
+    assert_eq!(call_target(Some(&arguments(json!({ "method": "POST" })))), (None, None));
+    assert_eq!(call_target(None), (None, None));
+  }
+
+  #[test]
+  fn replayed_results_are_marked_in_meta() {
+    let result = replayed(json_result(json!({ "id": 1 }))).unwrap();
+    assert!(is_replayed(&result));
+    assert!(!is_replayed(&json_result(json!({ "id": 1 })).unwrap()));
+  }
 }
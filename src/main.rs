@@ -1,10 +1,12 @@
 use anyhow::Result;
-use gitlab_mcp::{config::Config, telemetry, Server};
+use clap::Parser;
+use gitlab_mcp::{cli::Cli, config::Config, telemetry, Server};
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    // Load configuration
-    let config = Config::load()?;
+    // Parse command-line flags and load configuration
+    let cli = Cli::parse();
+    let config = Config::load(&cli)?;
 
     // Initialize telemetry
     let _guard = telemetry::init(&config.telemetry)?;
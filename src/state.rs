@@ -1,33 +1,197 @@
-use std::time::Instant;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
 use anyhow::{Context, Result};
 use dotenvy::dotenv;
+use rmcp::model::ErrorData as McpError;
+use serde_json::Value;
+use crate::audit::AuditWebhook;
 use crate::config::Config;
-use crate::gitlab::GitLabClient;
+use crate::gitlab::{GitLabClient, ProjectFilter, DEFAULT_USER_AGENT};
+use crate::tools::gitlab::{diff_refs, diff_refs_from_versions, set_instance_url};
 
-#[cfg(feature = "database")]
-use std::sync::Arc;
+/// How long a create result is replayed for a repeated idempotency key.
+const IDEMPOTENCY_TTL: Duration = Duration::from_secs(10 * 60);
+
+/// The result stored under one idempotency key. Its lock is held by the
+/// call that owns the key until that call records a result or gives up.
+type IdempotencySlot = Arc<tokio::sync::Mutex<Option<Value>>>;
+
+/// Outcome of claiming an idempotency key.
+pub enum Idempotency {
+  /// An earlier call with the key already created this result.
+  Replay(Value),
+  /// This call owns the key. Later calls with the same key wait until it is
+  /// completed, or dropped after a failure, and then retry.
+  Reserved(IdempotencyReservation),
+}
+
+pub struct IdempotencyReservation {
+  slot: tokio::sync::OwnedMutexGuard<Option<Value>>,
+}
+
+impl IdempotencyReservation {
+  /// Store the created result for replay to later calls with the same key.
+  pub fn complete(mut self, value: Value) {
+    *self.slot = Some(value);
+  }
+}
+
+/// Idempotency keys seen in the last `IDEMPOTENCY_TTL`, with their results.
+#[derive(Clone, Default)]
+struct IdempotentResults(Arc<Mutex<HashMap<String, (Instant, IdempotencySlot)>>>);
+
+impl IdempotentResults {
+  async fn reserve(&self, key: String) -> Idempotency {
+    let slot = {
+      let mut results = self.0.lock().unwrap();
+      results.retain(|_, (claimed_at, _)| claimed_at.elapsed() < IDEMPOTENCY_TTL);
+      results.entry(key).or_insert_with(|| (Instant::now(), Arc::default())).1.clone()
+    };
+    let slot = slot.lock_owned().await;
+    match slot.as_ref() {
+      Some(value) => Idempotency::Replay(value.clone()),
+      None => Idempotency::Reserved(IdempotencyReservation { slot }),
+    }
+  }
+}
+
+/// Defaults for the merge request changes/diff_refs cache.
+const DEFAULT_MERGE_REQUEST_CACHE_TTL_SECS: u64 = 60;
+const DEFAULT_MERGE_REQUEST_CACHE_SIZE: usize = 32;
+
+/// Changes and diff_refs of recently used merge requests, so a review that
+/// posts many discussions fetches them once. Entries expire after `ttl`;
+/// the oldest entry is evicted once `capacity` is reached.
+#[derive(Clone)]
+struct MergeRequestCache {
+  /// Keyed by project ID and IID, so every way of naming a project shares
+  /// one entry
+  entries: Arc<Mutex<HashMap<(u64, u64), CachedMergeRequest>>>,
+  ttl: Duration,
+  capacity: usize,
+}
+
+#[derive(Default)]
+struct CachedMergeRequest {
+  stored_at: Option<Instant>,
+  changes: Option<Value>,
+  diff_refs: Option<Value>,
+}
+
+impl MergeRequestCache {
+  fn new(ttl: Duration, capacity: usize) -> Self {
+    Self { entries: Arc::default(), ttl, capacity }
+  }
+
+  fn get(&self, project_id: u64, iid: u64, field: fn(&CachedMergeRequest) -> &Option<Value>) -> Option<Value> {
+    let mut entries = self.entries.lock().unwrap();
+    entries.retain(|_, entry| entry.stored_at.is_some_and(|stored_at| stored_at.elapsed() < self.ttl));
+    entries.get(&(project_id, iid)).and_then(|entry| field(entry).clone())
+  }
+
+  fn put(&self, project_id: u64, iid: u64, update: impl FnOnce(&mut CachedMergeRequest)) {
+    if self.capacity == 0 {
+      return;
+    }
+    let mut entries = self.entries.lock().unwrap();
+    let key = (project_id, iid);
+    if !entries.contains_key(&key) && entries.len() >= self.capacity {
+      let oldest = entries.iter().min_by_key(|(_, entry)| entry.stored_at).map(|(key, _)| *key);
+      if let Some(oldest) = oldest {
+        entries.remove(&oldest);
+      }
+    }
+    let entry = entries.entry(key).or_default();
+    entry.stored_at.get_or_insert_with(Instant::now);
+    update(entry);
+  }
+
+  fn remove(&self, project_id: u64, iid: u64) {
+    self.entries.lock().unwrap().remove(&(project_id, iid));
+  }
+
+  fn clear(&self) {
+    self.entries.lock().unwrap().clear();
+  }
+}
 
 #[derive(Clone)]
 pub struct ServerState {
   start_time: Instant,
   pub gitlab: GitLabClient,
+  user_ids: Arc<RwLock<HashMap<String, u64>>>,
+  current_username: Arc<RwLock<Option<String>>>,
+  projects: Arc<RwLock<HashMap<String, Value>>>,
+  active_calls: Arc<AtomicUsize>,
+  idempotent_results: IdempotentResults,
+  pub audit_webhook: Option<AuditWebhook>,
+  fallback_branch: Option<String>,
+  merge_requests: MergeRequestCache,
   // Add your shared state here
   #[cfg(feature = "database")]
   pub db: Option<Arc<sqlx::SqlitePool>>,
 }
 
 impl ServerState {
-  pub async fn new(_config: &Config) -> Result<Self> {
+  pub async fn new(config: &Config) -> Result<Self> {
     dotenv().ok();
 
     let base_url = dotenvy::var("GITLAB_URL").context("GITLAB_URL environment variable is required")?;
-    let token = dotenvy::var("GITLAB_TOKEN").context("GITLAB_TOKEN environment variable is required")?;
-    let gitlab = GitLabClient::new(base_url, token)?;
+    // May be unset when GITLAB_URL carries the credentials instead.
+    let token = dotenvy::var("GITLAB_TOKEN").unwrap_or_default();
+    let projects = ProjectFilter::new(
+      config.server.allowed_projects.clone(),
+      config.server.denied_projects.clone(),
+    );
+    let mut gitlab = GitLabClient::new(base_url, token)?
+      .with_dry_run(config.server.dry_run)
+      .with_project_filter(projects)
+      .with_extra_headers(&config.gitlab.extra_headers)?
+      .with_diffs_endpoint(config.gitlab.diffs_endpoint)
+      .with_connection(&config.gitlab.connection)?;
+    if let Some(user_agent) = &config.gitlab.user_agent {
+      gitlab = gitlab.with_user_agent(user_agent)?;
+    }
+    if let Some(per_second) = config.server.requests_per_second {
+      gitlab = gitlab.with_rate_limit(per_second);
+    }
+    tracing::info!("GitLab API base URL: {}", gitlab.base_url());
+    set_instance_url(gitlab.base_url());
+    if config.gitlab.connection.accept_invalid_certs {
+      tracing::warn!(
+        "INSECURE: TLS certificate verification is DISABLED for {} (--insecure / GITLAB_INSECURE). \
+Traffic, including the GitLab token, can be intercepted. Use this only against a local development instance.",
+        gitlab.base_url()
+      );
+    }
+    let user_agent = config.gitlab.user_agent.as_deref().unwrap_or(DEFAULT_USER_AGENT);
+    let audit_webhook = config
+      .audit
+      .webhook_url
+      .clone()
+      .map(|url| AuditWebhook::new(url, user_agent))
+      .transpose()?;
+    let merge_request_cache = MergeRequestCache::new(
+      Duration::from_secs(
+        config.server.merge_request_cache_ttl_secs.unwrap_or(DEFAULT_MERGE_REQUEST_CACHE_TTL_SECS),
+      ),
+      config.server.merge_request_cache_size.unwrap_or(DEFAULT_MERGE_REQUEST_CACHE_SIZE),
+    );
 
     #[cfg(feature = "database")]
     let mut state = Self {
       start_time: Instant::now(),
       gitlab,
+      user_ids: Arc::default(),
+      current_username: Arc::default(),
+      projects: Arc::default(),
+      active_calls: Arc::default(),
+      idempotent_results: IdempotentResults::default(),
+      audit_webhook,
+      fallback_branch: config.server.fallback_branch.clone(),
+      merge_requests: merge_request_cache,
       db: None,
     };
 
@@ -35,11 +199,20 @@ impl ServerState {
     let state = Self {
       start_time: Instant::now(),
       gitlab,
+      user_ids: Arc::default(),
+      current_username: Arc::default(),
+      projects: Arc::default(),
+      active_calls: Arc::default(),
+      idempotent_results: IdempotentResults::default(),
+      audit_webhook,
+      fallback_branch: config.server.fallback_branch.clone(),
+      merge_requests: merge_request_cache,
     };
 
     #[cfg(feature = "database")]
-    if let Some(db_config) = &_config.database {
+    if let Some(db_config) = &config.database {
       let pool = sqlx::SqlitePool::connect(&db_config.url).await?;
+      crate::audit::init_table(&pool).await?;
       state.db = Some(Arc::new(pool));
     }
 
@@ -49,4 +222,229 @@ impl ServerState {
   pub fn uptime(&self) -> std::time::Duration {
     self.start_time.elapsed()
   }
+
+  /// Claim an idempotency key before writing. While another call holds the
+  /// key this waits for it, then replays its result if it recorded one.
+  pub async fn reserve_idempotency_key(&self, key: String) -> Idempotency {
+    self.idempotent_results.reserve(key).await
+  }
+
+  /// Mark a tool call as in flight until the returned guard is dropped.
+  pub fn begin_call(&self) -> ActiveCallGuard {
+    self.active_calls.fetch_add(1, Ordering::SeqCst);
+    ActiveCallGuard(self.active_calls.clone())
+  }
+
+  pub fn active_calls(&self) -> usize {
+    self.active_calls.load(Ordering::SeqCst)
+  }
+
+  /// Wait for in-flight tool calls to finish, up to `grace`. Returns whether
+  /// everything drained in time.
+  pub async fn drain(&self, grace: Duration) -> bool {
+    let deadline = tokio::time::Instant::now() + grace;
+    while self.active_calls() > 0 {
+      if tokio::time::Instant::now() >= deadline {
+        return false;
+      }
+      tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    true
+  }
+
+  /// The username of the token owner, looked up once and cached for the
+  /// lifetime of the server.
+  pub async fn current_username(&self) -> Result<String, McpError> {
+    if let Some(username) = self.current_username.read().unwrap().as_ref() {
+      return Ok(username.clone());
+    }
+
+    let user = self.gitlab.get_current_user().await?;
+    let username = user
+      .get("username")
+      .and_then(Value::as_str)
+      .ok_or_else(|| McpError::internal_error("GitLab /user response has no username", None))?
+      .to_string();
+    *self.current_username.write().unwrap() = Some(username.clone());
+    Ok(username)
+  }
+
+  /// Resolve a GitLab username to its numeric user ID, caching hits for the
+  /// lifetime of the server.
+  pub async fn resolve_user_id(&self, username: &str) -> Result<u64, McpError> {
+    let username = username.trim().trim_start_matches('@');
+    if let Some(id) = self.user_ids.read().unwrap().get(username) {
+      return Ok(*id);
+    }
+
+    let users = self.gitlab.find_users_by_username(username).await?;
+    let id = users
+      .as_array()
+      .and_then(|users| users.first())
+      .and_then(|user| user.get("id"))
+      .and_then(Value::as_u64)
+      .ok_or_else(|| McpError::invalid_params(
+        format!("GitLab user '{}' not found", username),
+        None,
+      ))?;
+
+    self.user_ids.write().unwrap().insert(username.to_string(), id);
+    Ok(id)
+  }
+
+  /// Fetch project metadata from GitLab and refresh the cached copy.
+  pub async fn refresh_project(&self, project: &str) -> Result<Value, McpError> {
+    let value = self.gitlab.get_project(project).await?;
+    self.projects.write().unwrap().insert(project.to_string(), value.clone());
+    Ok(value)
+  }
+
+  /// Project metadata, served from cache after the first lookup.
+  pub async fn project(&self, project: &str) -> Result<Value, McpError> {
+    if let Some(value) = self.projects.read().unwrap().get(project) {
+      return Ok(value.clone());
+    }
+    self.refresh_project(project).await
+  }
+
+  /// A merge request's changes, served from the merge request cache while
+  /// fresh.
+  pub async fn merge_request_changes(&self, project: &str, merge_request_iid: u64) -> Result<Value, McpError> {
+    let project_id = self.gitlab.project_id(project).await?;
+    if let Some(changes) = self.merge_requests.get(project_id, merge_request_iid, |entry| &entry.changes) {
+      return Ok(changes);
+    }
+    let changes = self.gitlab.get_merge_request_changes(project, merge_request_iid).await?;
+    self.merge_requests.put(project_id, merge_request_iid, |entry| entry.changes = Some(changes.clone()));
+    Ok(changes)
+  }
+
+  /// A merge request's diff_refs, cached like `merge_request_changes`.
+  /// Falls back to the latest diff version for merge requests whose
+  /// `diff_refs` GitLab has not filled in yet.
+  pub async fn merge_request_diff_refs(&self, project: &str, merge_request_iid: u64) -> Result<Value, McpError> {
+    let project_id = self.gitlab.project_id(project).await?;
+    if let Some(refs) = self.merge_requests.get(project_id, merge_request_iid, |entry| &entry.diff_refs) {
+      return Ok(refs);
+    }
+    let merge_request = self.gitlab.get_merge_request(project, merge_request_iid).await?;
+    let refs = match diff_refs(&merge_request) {
+      Some(refs) => refs,
+      None => {
+        let versions = self.gitlab.get_merge_request_versions(project, merge_request_iid).await?;
+        diff_refs_from_versions(&versions).ok_or_else(|| {
+          McpError::invalid_params("Merge request has no diff versions yet", None)
+        })?
+      }
+    };
+    self.merge_requests.put(project_id, merge_request_iid, |entry| entry.diff_refs = Some(refs.clone()));
+    Ok(refs)
+  }
+
+  /// Drop one cached merge request, after a write to it. When the project
+  /// cannot be resolved to its ID, every entry is dropped instead.
+  pub async fn invalidate_merge_request(&self, project: &str, merge_request_iid: u64) {
+    match self.gitlab.project_id(project).await {
+      Ok(project_id) => self.merge_requests.remove(project_id, merge_request_iid),
+      Err(_) => self.merge_requests.clear(),
+    }
+  }
+
+  /// Drop every cached merge request, after a write that may have moved a
+  /// branch head without naming the merge request.
+  pub fn invalidate_merge_requests(&self) {
+    self.merge_requests.clear();
+  }
+
+  /// The project's default branch, used when a caller omits `ref`.
+  pub async fn default_branch(&self, project: &str) -> Result<String, McpError> {
+    self
+      .project(project)
+      .await?
+      .get("default_branch")
+      .and_then(Value::as_str)
+      .map(str::to_string)
+      .or_else(|| self.fallback_branch.clone())
+      .ok_or_else(|| McpError::invalid_params(
+        format!("GitLab project '{}' has no default branch", project),
+        None,
+      ))
+  }
+}
+
+pub struct ActiveCallGuard(Arc<AtomicUsize>);
+
+impl Drop for ActiveCallGuard {
+  fn drop(&mut self) {
+    self.0.fetch_sub(1, Ordering::SeqCst);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use serde_json::json;
+
+  #[test]
+  fn merge_request_cache_evicts_oldest_entry_when_full() {
+    let cache = MergeRequestCache::new(Duration::from_secs(60), 2);
+    cache.put(10, 1, |entry| entry.changes = Some(json!(1)));
+    cache.put(10, 2, |entry| entry.changes = Some(json!(2)));
+    cache.put(10, 2, |entry| entry.diff_refs = Some(json!("refs")));
+    cache.put(10, 3, |entry| entry.changes = Some(json!(3)));
+
+    assert_eq!(cache.get(10, 1, |entry| &entry.changes), None);
+    assert_eq!(cache.get(10, 2, |entry| &entry.changes), Some(json!(2)));
+    assert_eq!(cache.get(10, 2, |entry| &entry.diff_refs), Some(json!("refs")));
+    assert_eq!(cache.get(10, 3, |entry| &entry.changes), Some(json!(3)));
+  }
+
+  #[test]
+  fn merge_request_cache_removes_only_the_named_merge_request() {
+    let cache = MergeRequestCache::new(Duration::from_secs(60), 4);
+    cache.put(10, 1, |entry| entry.changes = Some(json!(1)));
+    cache.put(10, 2, |entry| entry.changes = Some(json!(2)));
+    cache.put(20, 1, |entry| entry.changes = Some(json!(3)));
+
+    cache.remove(10, 1);
+    assert_eq!(cache.get(10, 1, |entry| &entry.changes), None);
+    assert_eq!(cache.get(10, 2, |entry| &entry.changes), Some(json!(2)));
+    assert_eq!(cache.get(20, 1, |entry| &entry.changes), Some(json!(3)));
+  }
+
+  #[test]
+  fn merge_request_cache_expires_and_can_be_disabled() {
+    let expired = MergeRequestCache::new(Duration::ZERO, 2);
+    expired.put(10, 1, |entry| entry.changes = Some(json!(1)));
+    assert_eq!(expired.get(10, 1, |entry| &entry.changes), None);
+
+    let disabled = MergeRequestCache::new(Duration::from_secs(60), 0);
+    disabled.put(10, 1, |entry| entry.changes = Some(json!(1)));
+    assert_eq!(disabled.get(10, 1, |entry| &entry.changes), None);
+  }
+
+  #[tokio::test]
+  async fn idempotency_key_makes_concurrent_retries_wait_and_replay() {
+    let results = IdempotentResults::default();
+    let Idempotency::Reserved(first) = results.reserve("note:1".to_string()).await else {
+      panic!("first call should own the key");
+    };
+    let retry = tokio::spawn({
+      let results = results.clone();
+      async move { results.reserve("note:1".to_string()).await }
+    });
+    tokio::task::yield_now().await;
+    assert!(!retry.is_finished(), "retry must wait for the call in flight");
+
+    first.complete(json!({ "id": 7 }));
+    assert!(matches!(retry.await.unwrap(), Idempotency::Replay(value) if value == json!({ "id": 7 })));
+  }
+
+  #[tokio::test]
+  async fn failed_call_releases_its_idempotency_key() {
+    let results = IdempotentResults::default();
+    let first = results.reserve("note:1".to_string()).await;
+    drop(first);
+    assert!(matches!(results.reserve("note:1".to_string()).await, Idempotency::Reserved(_)));
+  }
 }
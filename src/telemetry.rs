@@ -1,18 +1,27 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 use crate::config::{TelemetryConfig, LogFormat};
-use anyhow::Result;
+use anyhow::{Context, Result};
 
 pub fn init(config: &TelemetryConfig) -> Result<tracing_appender::non_blocking::WorkerGuard> {
   let env_filter = tracing_subscriber::EnvFilter::try_from_default_env()
     .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new(&config.level));
 
   let (non_blocking, guard) = if let Some(file_path) = &config.file {
-    let file_appender = tracing_appender::rolling::daily("logs", file_path);
+    let file_appender = RotatingFile::open(file_path, config.max_file_bytes(), config.max_files())
+      .with_context(|| format!("Failed to open log file {}", file_path))?;
     tracing_appender::non_blocking(file_appender)
   } else {
     tracing_appender::non_blocking(std::io::stdout())
   };
 
+  let ansi = config.color.unwrap_or_else(|| {
+    let no_color = std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+    !no_color && config.file.is_none() && io::stdout().is_terminal()
+  });
+
   let subscriber = tracing_subscriber::registry()
     .with(env_filter);
 
@@ -20,12 +29,14 @@ pub fn init(config: &TelemetryConfig) -> Result<tracing_appender::non_blocking::
     LogFormat::Pretty => {
       subscriber.with(tracing_subscriber::fmt::layer()
         .with_writer(non_blocking)
+        .with_ansi(ansi)
         .pretty())
         .init();
     }
     LogFormat::Json => {
       subscriber.with(tracing_subscriber::fmt::layer()
         .with_writer(non_blocking)
+        .with_ansi(ansi)
         .json())
         .init();
     }
@@ -33,3 +44,66 @@ pub fn init(config: &TelemetryConfig) -> Result<tracing_appender::non_blocking::
 
   Ok(guard)
 }
+
+/// Log file that rolls over by size. When a write would push the file past
+/// `max_bytes`, `log` becomes `log.1`, `log.1` becomes `log.2`, and so on;
+/// anything past `max_files` is deleted.
+struct RotatingFile {
+  path: PathBuf,
+  file: File,
+  written: u64,
+  max_bytes: u64,
+  max_files: usize,
+}
+
+impl RotatingFile {
+  fn open(path: impl AsRef<Path>, max_bytes: u64, max_files: usize) -> io::Result<Self> {
+    let path = path.as_ref().to_path_buf();
+    if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+      fs::create_dir_all(parent)?;
+    }
+    let file = OpenOptions::new().create(true).append(true).open(&path)?;
+    let written = file.metadata()?.len();
+    Ok(Self { path, file, written, max_bytes, max_files })
+  }
+
+  fn rotated_path(&self, index: usize) -> PathBuf {
+    let mut name = self.path.clone().into_os_string();
+    name.push(format!(".{}", index));
+    PathBuf::from(name)
+  }
+
+  fn rotate(&mut self) -> io::Result<()> {
+    self.file.flush()?;
+    if self.max_files == 0 {
+      fs::remove_file(&self.path)?;
+    } else {
+      let _ = fs::remove_file(self.rotated_path(self.max_files));
+      for index in (1..self.max_files).rev() {
+        let from = self.rotated_path(index);
+        if from.exists() {
+          fs::rename(&from, self.rotated_path(index + 1))?;
+        }
+      }
+      fs::rename(&self.path, self.rotated_path(1))?;
+    }
+    self.file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+    self.written = 0;
+    Ok(())
+  }
+}
+
+impl Write for RotatingFile {
+  fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+    if self.written > 0 && self.written + buf.len() as u64 > self.max_bytes {
+      self.rotate()?;
+    }
+    let written = self.file.write(buf)?;
+    self.written += written as u64;
+    Ok(written)
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    self.file.flush()
+  }
+}
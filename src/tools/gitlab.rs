@@ -1,32 +1,414 @@
 use rmcp::model::{CallToolResult, Content, ErrorData as McpError};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use chrono::{DateTime, Utc};
 use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::sync::OnceLock;
+
+use crate::config::ReviewConfig;
+use crate::gitlab::{glob_match, is_dry_run_response, Page, PageWalk};
 
 #[derive(Debug, Deserialize, JsonSchema)]
+#[serde(try_from = "RawMergeRequestLocator")]
 pub struct MergeRequestLocator {
-  /// Project ID or full path (e.g. "group/project")
   pub project: String,
-  /// Merge request IID
   pub merge_request_iid: u64,
 }
 
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct RawMergeRequestLocator {
+  /// Project ID or full path (e.g. "group/project"). A merge request URL
+  /// such as "https://gitlab.com/group/project/-/merge_requests/42" is also
+  /// accepted and supplies the IID; it must be on the server's GitLab host.
+  /// Required unless `namespace` and `name` are given instead.
+  #[serde(default)]
+  pub project: Option<String>,
+  /// Group or user namespace (e.g. "group/subgroup"); use with `name`
+  /// instead of `project`
+  #[serde(default)]
+  pub namespace: Option<String>,
+  /// Project name within `namespace` (e.g. "project")
+  #[serde(default)]
+  pub name: Option<String>,
+  /// Merge request IID; optional when `project` is a merge request URL
+  #[serde(default)]
+  pub merge_request_iid: Option<u64>,
+}
+
+impl RawMergeRequestLocator {
+  /// `project`, or `namespace/name` when the project is given in two parts.
+  fn project_path(&mut self) -> Result<String, String> {
+    match (self.project.take(), self.namespace.take(), self.name.take()) {
+      (Some(project), None, None) => Ok(project),
+      (None, Some(namespace), Some(name)) => {
+        let namespace = namespace.trim().trim_matches('/');
+        let name = name.trim().trim_matches('/');
+        if namespace.is_empty() || name.is_empty() {
+          return Err("namespace and name must not be empty".to_string());
+        }
+        Ok(format!("{}/{}", namespace, name))
+      }
+      (None, None, None) => Err("missing field `project`".to_string()),
+      (Some(_), _, _) => Err("give either project or namespace and name, not both".to_string()),
+      (None, _, _) => Err("namespace and name must be given together".to_string()),
+    }
+  }
+}
+
+impl TryFrom<RawMergeRequestLocator> for MergeRequestLocator {
+  type Error = String;
+
+  fn try_from(mut raw: RawMergeRequestLocator) -> Result<Self, Self::Error> {
+    let project = raw.project_path()?;
+    let (project, url_iid) = match parse_gitlab_url(&project, INSTANCE_URL.get().map(String::as_str))? {
+      Some(parsed) => parsed,
+      None => (project, None),
+    };
+    let merge_request_iid = match (raw.merge_request_iid, url_iid) {
+      (Some(given), Some(from_url)) if given != from_url => {
+        return Err(format!(
+          "merge_request_iid {} does not match merge request {} in the project URL",
+          given, from_url
+        ));
+      }
+      (Some(iid), _) | (None, Some(iid)) => iid,
+      (None, None) => return Err("missing field `merge_request_iid`".to_string()),
+    };
+    let locator = Self { project, merge_request_iid };
+    locator.validate().map_err(|err| err.message.into_owned())?;
+    Ok(locator)
+  }
+}
+
+impl MergeRequestLocator {
+  /// Rejects values that can never name a merge request, such as a zero IID
+  /// or a placeholder empty project, before any request reaches GitLab.
+  pub fn validate(&self) -> Result<(), McpError> {
+    let project = self.project.trim();
+    if project.is_empty() {
+      return Err(McpError::invalid_params("project must not be empty", None));
+    }
+    if project == "0" {
+      return Err(McpError::invalid_params("project ID must be greater than 0", None));
+    }
+    if self.merge_request_iid == 0 {
+      return Err(McpError::invalid_params(
+        "merge_request_iid must be greater than 0; use the IID shown in the merge request URL",
+        None,
+      ));
+    }
+    Ok(())
+  }
+}
+
+/// The GitLab instance URL the server talks to, set once at startup so that
+/// merge request URLs of an instance under a subpath parse correctly.
+static INSTANCE_URL: OnceLock<String> = OnceLock::new();
+
+/// Records the instance (or API base) URL for `MergeRequestLocator` to
+/// strip from merge request URLs. Only the first call has any effect.
+pub fn set_instance_url(url: &str) {
+  let _ = INSTANCE_URL.set(url.to_string());
+}
+
+/// `host` and path of an http(s) URL, without query or fragment.
+fn split_http_url(value: &str) -> Option<(&str, &str)> {
+  let value = value.trim();
+  let rest = value
+    .strip_prefix("https://")
+    .or_else(|| value.strip_prefix("http://"))?;
+  let rest = rest.split(['?', '#']).next().unwrap_or_default();
+  Some(rest.split_once('/').unwrap_or((rest, "")))
+}
+
+/// Splits a GitLab web URL into the project path and, for merge request
+/// URLs, the IID. The instance's subpath (e.g. `/gitlab`) is not part of the
+/// project. A URL on another host than `instance` is an error: its path
+/// would name whatever project happens to live there on this instance.
+/// Returns `Ok(None)` for anything that is not an http(s) URL.
+fn parse_gitlab_url(value: &str, instance: Option<&str>) -> Result<Option<(String, Option<u64>)>, String> {
+  let Some((host, path)) = split_http_url(value) else {
+    return Ok(None);
+  };
+  let mut path = path.trim_matches('/');
+  if let Some((instance_host, instance_path)) = instance.and_then(split_http_url) {
+    if !host.eq_ignore_ascii_case(instance_host) {
+      return Err(format!(
+        "{} is on {}, but this server is connected to {}; pass the project path or ID instead",
+        value.trim(),
+        host,
+        instance_host
+      ));
+    }
+    let instance_path = instance_path.trim_matches('/');
+    let instance_path = instance_path
+      .strip_suffix("api/v4")
+      .or_else(|| instance_path.strip_suffix("api"))
+      .unwrap_or(instance_path)
+      .trim_end_matches('/');
+    if !instance_path.is_empty() {
+      if let Some(rest) = path.strip_prefix(instance_path).and_then(|rest| rest.strip_prefix('/')) {
+        path = rest;
+      }
+    }
+  }
+
+  let (project, tail) = match path.split_once("/-/") {
+    Some((project, tail)) => (project, Some(tail)),
+    None => match path.split_once("/merge_requests/") {
+      Some((project, tail)) => (project, Some(tail)),
+      None => (path, None),
+    },
+  };
+  if project.is_empty() {
+    return Ok(None);
+  }
+  let iid = tail
+    .map(|tail| tail.strip_prefix("merge_requests/").unwrap_or(tail))
+    .and_then(|tail| tail.split('/').next())
+    .and_then(|iid| iid.parse().ok());
+  Ok(Some((project.to_string(), iid)))
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ProjectLocator {
+  /// Project ID or full path (e.g. "group/project")
+  pub project: String,
+}
+
+/// GitLab caps `per_page` at 100 for every list endpoint.
+pub const MAX_PER_PAGE: u32 = 100;
+
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+pub struct Pagination {
+  /// Page number to fetch (1-based)
+  #[serde(default)]
+  pub page: Option<u32>,
+  /// Results per page (GitLab default 20, max 100; larger values are clamped)
+  #[serde(default)]
+  pub per_page: Option<u32>,
+}
+
+/// Options every write tool accepts.
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+pub struct WriteOptions {
+  /// Preview the write: return the URL and payload instead of calling GitLab
+  #[serde(default)]
+  pub dry_run: Option<bool>,
+  /// Act as this GitLab username or user ID via the Sudo header (requires an admin token)
+  #[serde(default)]
+  pub sudo: Option<String>,
+}
+
+/// Trims metadata results to the keys a caller asked for.
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+pub struct Projection {
+  /// Only return these top-level keys of each returned object; unknown keys are ignored
+  #[serde(default)]
+  pub fields: Option<Vec<String>>,
+}
+
+impl Pagination {
+  pub fn page(&self) -> Option<u32> {
+    self.page.map(|page| page.max(1))
+  }
+
+  pub fn per_page(&self) -> Option<u32> {
+    self.per_page.map(|per_page| per_page.clamp(1, MAX_PER_PAGE))
+  }
+}
+
 #[derive(Debug, Deserialize, JsonSchema)]
 pub struct GetMergeRequestRequest {
   #[serde(flatten)]
   pub locator: MergeRequestLocator,
+  /// Return GitLab's full merge request object instead of the normalized fields
+  #[serde(default)]
+  pub include_raw: Option<bool>,
+  #[serde(flatten)]
+  pub projection: Projection,
+}
+
+/// The fields of a GitLab merge request that tools rely on. Anything else
+/// GitLab sends is kept in `extra` for code that needs it, but is not
+/// serialized, so the shape agents see stays stable across GitLab versions.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct MergeRequest {
+  pub iid: u64,
+  #[serde(default)]
+  pub title: String,
+  #[serde(default)]
+  pub description: Option<String>,
+  #[serde(default)]
+  pub state: String,
+  #[serde(default)]
+  pub author: Option<UserRef>,
+  #[serde(default)]
+  pub source_branch: String,
+  #[serde(default)]
+  pub target_branch: String,
+  #[serde(default)]
+  pub draft: bool,
+  #[serde(default)]
+  pub merge_status: Option<String>,
+  #[serde(default)]
+  pub detailed_merge_status: Option<String>,
+  /// `detailed_merge_status`, or the older `merge_status`, normalized
+  #[serde(default, skip_deserializing)]
+  pub merge_state: Option<MergeState>,
+  #[serde(default)]
+  pub labels: Vec<String>,
+  #[serde(default)]
+  pub web_url: String,
+  #[serde(default)]
+  pub diff_refs: Option<DiffRefs>,
+  #[serde(default)]
+  pub updated_at: Option<String>,
+  #[serde(flatten, skip_serializing)]
+  pub extra: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct UserRef {
+  pub id: u64,
+  pub username: String,
+  #[serde(default)]
+  pub name: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct DiffRefs {
+  pub base_sha: Option<String>,
+  pub head_sha: Option<String>,
+  pub start_sha: Option<String>,
+}
+
+/// Whether a merge request can be merged, as reported by GitLab's
+/// `detailed_merge_status` (GitLab 15.6+) or, on older instances, the
+/// coarser `merge_status`. Values GitLab adds later land in `Unknown`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MergeState {
+  Mergeable,
+  Checking,
+  Unchecked,
+  Conflict,
+  NeedRebase,
+  CiMustPass,
+  CiStillRunning,
+  NotApproved,
+  RequestedChanges,
+  DraftStatus,
+  DiscussionsNotResolved,
+  BlockedStatus,
+  ExternalStatusChecks,
+  NotOpen,
+  Unknown(String),
+}
+
+impl MergeState {
+  /// Reads `detailed_merge_status` when present, else maps the legacy
+  /// `merge_status` values onto the detailed ones.
+  pub fn from_merge_request(merge_request: &Value) -> Option<Self> {
+    if let Some(detailed) = merge_request.get("detailed_merge_status").and_then(Value::as_str) {
+      return Some(Self::parse(detailed));
+    }
+    let legacy = merge_request.get("merge_status").and_then(Value::as_str)?;
+    Some(match legacy {
+      "can_be_merged" => MergeState::Mergeable,
+      "cannot_be_merged" => MergeState::Conflict,
+      "cannot_be_merged_recheck" => MergeState::Unchecked,
+      other => Self::parse(other),
+    })
+  }
+
+  pub fn parse(value: &str) -> Self {
+    match value {
+      "mergeable" => MergeState::Mergeable,
+      "checking" => MergeState::Checking,
+      "unchecked" => MergeState::Unchecked,
+      "conflict" | "broken_status" => MergeState::Conflict,
+      "need_rebase" => MergeState::NeedRebase,
+      "ci_must_pass" => MergeState::CiMustPass,
+      "ci_still_running" => MergeState::CiStillRunning,
+      "not_approved" => MergeState::NotApproved,
+      "requested_changes" => MergeState::RequestedChanges,
+      "draft_status" => MergeState::DraftStatus,
+      "discussions_not_resolved" => MergeState::DiscussionsNotResolved,
+      "blocked_status" => MergeState::BlockedStatus,
+      "external_status_checks" => MergeState::ExternalStatusChecks,
+      "not_open" => MergeState::NotOpen,
+      other => MergeState::Unknown(other.to_string()),
+    }
+  }
+
+  pub fn as_str(&self) -> &str {
+    match self {
+      MergeState::Mergeable => "mergeable",
+      MergeState::Checking => "checking",
+      MergeState::Unchecked => "unchecked",
+      MergeState::Conflict => "conflict",
+      MergeState::NeedRebase => "need_rebase",
+      MergeState::CiMustPass => "ci_must_pass",
+      MergeState::CiStillRunning => "ci_still_running",
+      MergeState::NotApproved => "not_approved",
+      MergeState::RequestedChanges => "requested_changes",
+      MergeState::DraftStatus => "draft_status",
+      MergeState::DiscussionsNotResolved => "discussions_not_resolved",
+      MergeState::BlockedStatus => "blocked_status",
+      MergeState::ExternalStatusChecks => "external_status_checks",
+      MergeState::NotOpen => "not_open",
+      MergeState::Unknown(value) => value,
+    }
+  }
+}
+
+impl Serialize for MergeState {
+  fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(self.as_str())
+  }
+}
+
+impl MergeRequest {
+  pub fn from_value(value: Value) -> Result<Self, McpError> {
+    let merge_state = MergeState::from_merge_request(&value);
+    let mut merge_request: Self = serde_json::from_value(value).map_err(|err| {
+      McpError::internal_error(
+        "GitLab returned an unexpected merge request shape",
+        Some(Value::String(err.to_string())),
+      )
+    })?;
+    merge_request.merge_state = merge_state;
+    Ok(merge_request)
+  }
+
+  pub fn into_value(self) -> Value {
+    serde_json::to_value(self).expect("merge request fields always serialize")
+  }
 }
 
 #[derive(Debug, Deserialize, JsonSchema)]
 pub struct GetMergeRequestChangesRequest {
   #[serde(flatten)]
   pub locator: MergeRequestLocator,
+  /// Approximate size budget for the response; the largest file diffs are
+  /// replaced with a truncation marker until the response fits
+  #[serde(default)]
+  pub max_bytes: Option<usize>,
+  /// Only return changes whose new_path or old_path matches one of these
+  /// paths; `*` matches any characters, so "src/**" covers everything under src
+  #[serde(default)]
+  pub paths: Option<Vec<String>>,
+  #[serde(flatten)]
+  pub projection: Projection,
 }
 
 #[derive(Debug, Deserialize, JsonSchema)]
 pub struct GetMergeRequestVersionsRequest {
   #[serde(flatten)]
   pub locator: MergeRequestLocator,
+  #[serde(flatten)]
+  pub projection: Projection,
 }
 
 #[derive(Debug, Deserialize, JsonSchema)]
@@ -35,11 +417,25 @@ pub struct CreateMergeRequestDiscussionRequest {
   pub locator: MergeRequestLocator,
   /// Markdown body of the discussion comment
   pub body: String,
+  /// Review label such as issue, nitpick, suggestion, question, or praise;
+  /// prepends the configured prefix (e.g. "**issue:**") to the body
+  #[serde(default)]
+  pub severity: Option<String>,
   /// Position payload for line-specific comments
   pub position: Value,
   /// Optionally resolve the discussion immediately
   #[serde(default)]
   pub resolve: Option<bool>,
+  /// Client-chosen key; repeating a call with the same key returns the
+  /// original result instead of posting again
+  #[serde(default)]
+  pub idempotency_key: Option<String>,
+  #[serde(flatten)]
+  pub write: WriteOptions,
+  /// Check that the position's line is part of the merge request diff before
+  /// posting (costs one extra request)
+  #[serde(default)]
+  pub validate_position: Option<bool>,
 }
 
 #[derive(Debug, Deserialize, JsonSchema)]
@@ -48,147 +444,2636 @@ pub struct CreateMergeRequestNoteRequest {
   pub locator: MergeRequestLocator,
   /// Markdown body of the note
   pub body: String,
+  /// Review label such as issue, nitpick, suggestion, question, or praise;
+  /// prepends the configured prefix (e.g. "**issue:**") to the body
+  #[serde(default)]
+  pub severity: Option<String>,
   /// Create a confidential note (visible only to project members with access)
   #[serde(default)]
   pub confidential: Option<bool>,
+  /// SHA of a commit in the merge request to anchor the note to
+  #[serde(default)]
+  pub commit_id: Option<String>,
+  /// Client-chosen key; repeating a call with the same key returns the
+  /// original result instead of posting again
+  #[serde(default)]
+  pub idempotency_key: Option<String>,
+  #[serde(flatten)]
+  pub write: WriteOptions,
 }
 
-#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+#[derive(Debug, Deserialize, JsonSchema)]
 #[serde(rename_all = "lowercase")]
-pub enum DiscussionPositionType {
-  Text,
-  Image,
+pub enum MilestoneState {
+  Active,
+  Closed,
 }
 
-#[derive(Debug, Deserialize, Serialize, JsonSchema)]
-#[serde(rename_all = "lowercase")]
-pub enum DiscussionLinePositionType {
-  New,
-  Old,
+impl MilestoneState {
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      MilestoneState::Active => "active",
+      MilestoneState::Closed => "closed",
+    }
+  }
 }
 
-#[derive(Debug, Deserialize, Serialize, JsonSchema)]
-pub struct DiscussionLineReference {
-  pub line_code: String,
-  #[serde(rename = "type")]
-  pub position_type: DiscussionLinePositionType,
-  #[serde(default, skip_serializing_if = "Option::is_none")]
-  pub old_line: Option<u32>,
-  #[serde(default, skip_serializing_if = "Option::is_none")]
-  pub new_line: Option<u32>,
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ListMilestonesRequest {
+  #[serde(flatten)]
+  pub locator: ProjectLocator,
+  /// Only return milestones in this state
+  #[serde(default)]
+  pub state: Option<MilestoneState>,
+  /// Only return milestones whose title or description matches this string
+  #[serde(default)]
+  pub search: Option<String>,
+  #[serde(flatten)]
+  pub pagination: Pagination,
+  #[serde(flatten)]
+  pub projection: Projection,
 }
 
-#[derive(Debug, Deserialize, Serialize, JsonSchema)]
-pub struct DiscussionLineRange {
-  pub start: DiscussionLineReference,
-  pub end: DiscussionLineReference,
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ListReleasesRequest {
+  #[serde(flatten)]
+  pub locator: ProjectLocator,
+  #[serde(flatten)]
+  pub pagination: Pagination,
+  #[serde(flatten)]
+  pub projection: Projection,
 }
 
-#[derive(Debug, Deserialize, Serialize, JsonSchema)]
-pub struct DiscussionPosition {
-  pub base_sha: String,
-  pub head_sha: String,
-  pub start_sha: String,
-  #[serde(default = "default_position_type")]
-  pub position_type: DiscussionPositionType,
-  pub new_path: String,
-  pub old_path: String,
-  #[serde(default, skip_serializing_if = "Option::is_none")]
-  pub new_line: Option<u32>,
-  #[serde(default, skip_serializing_if = "Option::is_none")]
-  pub old_line: Option<u32>,
-  #[serde(default, skip_serializing_if = "Option::is_none")]
-  pub line_range: Option<DiscussionLineRange>,
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct GetReleaseRequest {
+  #[serde(flatten)]
+  pub locator: ProjectLocator,
+  /// Tag name the release is attached to (e.g. "v1.2.0")
+  pub tag_name: String,
+  #[serde(flatten)]
+  pub projection: Projection,
 }
 
-fn default_position_type() -> DiscussionPositionType {
-  DiscussionPositionType::Text
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct CreateReleaseRequest {
+  #[serde(flatten)]
+  pub locator: ProjectLocator,
+  /// Tag to release; created from `ref` if it does not exist yet
+  pub tag_name: String,
+  /// Release title
+  pub name: String,
+  /// Markdown release notes
+  pub description: String,
+  /// Branch or commit SHA to create the tag from when the tag does not exist
+  #[serde(default, rename = "ref")]
+  pub git_ref: Option<String>,
+  /// Milestone titles to associate with the release
+  #[serde(default)]
+  pub milestones: Option<Vec<String>>,
+  #[serde(flatten)]
+  pub write: WriteOptions,
 }
 
-impl DiscussionPosition {
-  pub fn validate(&self) -> Result<(), McpError> {
-    if self.base_sha.trim().is_empty()
-      || self.head_sha.trim().is_empty()
-      || self.start_sha.trim().is_empty()
-    {
-      return Err(McpError::invalid_params(
-        "GitLab discussion position requires base_sha, head_sha, and start_sha",
-        None,
-      ));
-    }
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum SortDirection {
+  Asc,
+  Desc,
+}
 
-    if self.new_path.trim().is_empty() || self.old_path.trim().is_empty() {
-      return Err(McpError::invalid_params(
-        "GitLab discussion position requires both new_path and old_path",
-        None,
-      ));
+impl SortDirection {
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      SortDirection::Asc => "asc",
+      SortDirection::Desc => "desc",
     }
+  }
+}
 
-    let has_line = self.new_line.is_some() || self.old_line.is_some() || self.line_range.is_some();
-    if !has_line {
-      return Err(McpError::invalid_params(
-        "GitLab discussion position requires at least one of new_line, old_line, or line_range",
-        None,
-      ));
-    }
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum TagOrderBy {
+  Name,
+  Updated,
+  Version,
+}
 
-    Ok(())
+impl TagOrderBy {
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      TagOrderBy::Name => "name",
+      TagOrderBy::Updated => "updated",
+      TagOrderBy::Version => "version",
+    }
   }
 }
 
-fn parse_discussion_position(raw: &Value) -> Result<DiscussionPosition, McpError> {
-  let value = match raw {
-    Value::String(s) => serde_json::from_str::<Value>(s).map_err(|err| {
-      McpError::invalid_params("position string is not valid JSON", Some(Value::String(err.to_string())))
-    })?,
-    other => other.clone(),
-  };
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ListTagsRequest {
+  #[serde(flatten)]
+  pub locator: ProjectLocator,
+  /// Only return tags matching this string (`^term` and `term$` anchor the match)
+  #[serde(default)]
+  pub search: Option<String>,
+  /// Order tags by name, updated, or version
+  #[serde(default)]
+  pub order_by: Option<TagOrderBy>,
+  /// Sort direction (asc or desc)
+  #[serde(default)]
+  pub sort: Option<SortDirection>,
+  #[serde(flatten)]
+  pub pagination: Pagination,
+  #[serde(flatten)]
+  pub projection: Projection,
+}
 
-  serde_json::from_value::<DiscussionPosition>(value).map_err(|err| {
-    McpError::invalid_params(
-      "position must be a GitLab discussion position object",
-      Some(Value::String(err.to_string())),
-    )
-  })
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct CreateTagRequest {
+  #[serde(flatten)]
+  pub locator: ProjectLocator,
+  /// Name of the new tag
+  pub tag_name: String,
+  /// Branch name or commit SHA to tag (defaults to the project's default branch)
+  #[serde(default, rename = "ref")]
+  pub git_ref: Option<String>,
+  /// Optional message; creates an annotated tag when set
+  #[serde(default)]
+  pub message: Option<String>,
+  #[serde(flatten)]
+  pub write: WriteOptions,
 }
 
-pub fn map_to_payload(map: Map<String, Value>) -> Value {
-  Value::Object(map)
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct UpdateMergeRequestReviewersRequest {
+  #[serde(flatten)]
+  pub locator: MergeRequestLocator,
+  /// GitLab usernames (with or without a leading @)
+  pub usernames: Vec<String>,
+  #[serde(flatten)]
+  pub write: WriteOptions,
 }
 
-pub fn discussion_payload(req: &CreateMergeRequestDiscussionRequest) -> Result<Value, McpError> {
-  let position = parse_discussion_position(&req.position)?;
-  position.validate()?;
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct GetUserRequest {
+  /// GitLab username (with or without a leading @)
+  #[serde(default)]
+  pub username: Option<String>,
+  /// Numeric GitLab user ID
+  #[serde(default)]
+  pub id: Option<u64>,
+  #[serde(flatten)]
+  pub projection: Projection,
+}
 
-  let mut map = Map::new();
-  map.insert("body".to_string(), Value::String(req.body.clone()));
-  let position = serde_json::to_value(&position).map_err(|err| {
-    McpError::internal_error(
-      "Failed to serialize GitLab discussion position",
-      Some(Value::String(err.to_string())),
-    )
+impl GetUserRequest {
+  pub fn validate(&self) -> Result<(), McpError> {
+    match (&self.username, self.id) {
+      (Some(username), None) if !username.trim().is_empty() => Ok(()),
+      (None, Some(_)) => Ok(()),
+      _ => Err(McpError::invalid_params(
+        "Provide exactly one of username or id",
+        None,
+      )),
+    }
+  }
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SearchScope {
+  Blobs,
+  Commits,
+  Issues,
+  MergeRequests,
+  Notes,
+  /// Project names and paths (global search only)
+  Projects,
+}
+
+impl SearchScope {
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      SearchScope::Blobs => "blobs",
+      SearchScope::Commits => "commits",
+      SearchScope::Issues => "issues",
+      SearchScope::MergeRequests => "merge_requests",
+      SearchScope::Notes => "notes",
+      SearchScope::Projects => "projects",
+    }
+  }
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ProjectSearchRequest {
+  #[serde(flatten)]
+  pub locator: ProjectLocator,
+  /// What to search: blobs (code), commits, issues, merge_requests, or notes
+  pub scope: SearchScope,
+  /// Search term
+  pub search: String,
+  #[serde(flatten)]
+  pub pagination: Pagination,
+  #[serde(flatten)]
+  pub projection: Projection,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct GlobalSearchRequest {
+  /// What to search: blobs (code), commits, issues, merge_requests, notes, or projects
+  pub scope: SearchScope,
+  /// Search term
+  pub search: String,
+  #[serde(flatten)]
+  pub pagination: Pagination,
+  #[serde(flatten)]
+  pub projection: Projection,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct GetProjectRequest {
+  #[serde(flatten)]
+  pub locator: ProjectLocator,
+  #[serde(flatten)]
+  pub projection: Projection,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ListMembersRequest {
+  #[serde(flatten)]
+  pub locator: ProjectLocator,
+  /// Filter members by name, username, or email
+  #[serde(default)]
+  pub query: Option<String>,
+  #[serde(flatten)]
+  pub pagination: Pagination,
+  #[serde(flatten)]
+  pub projection: Projection,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct CommitOntoBranchRequest {
+  #[serde(flatten)]
+  pub locator: ProjectLocator,
+  /// Commit SHA to apply
+  pub sha: String,
+  /// Target branch the new commit is created on
+  pub branch: String,
+  #[serde(flatten)]
+  pub write: WriteOptions,
+}
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum CommitActionType {
+  Create,
+  Update,
+  Delete,
+  Move,
+}
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct CommitAction {
+  /// One of create, update, delete, move
+  pub action: CommitActionType,
+  /// Path of the file to act on
+  pub file_path: String,
+  /// Full new file content (required for create and update)
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub content: Option<String>,
+  /// Original path of the file (required for move)
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub previous_path: Option<String>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct CreateCommitRequest {
+  #[serde(flatten)]
+  pub locator: ProjectLocator,
+  /// Branch to commit to
+  pub branch: String,
+  /// Create `branch` from this branch first if it does not exist yet
+  #[serde(default)]
+  pub start_branch: Option<String>,
+  /// Commit message
+  pub commit_message: String,
+  /// File actions applied in order
+  pub actions: Vec<CommitAction>,
+  #[serde(flatten)]
+  pub write: WriteOptions,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ApplySuggestionRequest {
+  /// Suggestion ID (from the `suggestions` array of a discussion note)
+  pub suggestion_id: u64,
+  /// Custom commit message for the applied suggestion
+  #[serde(default)]
+  pub commit_message: Option<String>,
+  #[serde(flatten)]
+  pub write: WriteOptions,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct GetPipelineTestReportRequest {
+  #[serde(flatten)]
+  pub locator: ProjectLocator,
+  /// Pipeline ID
+  pub pipeline_id: u64,
+  /// Drop passing suites and test cases, keeping only failures and errors
+  #[serde(default)]
+  pub failed_only: Option<bool>,
+  #[serde(flatten)]
+  pub projection: Projection,
+}
+
+/// Default cap on each stack trace `get_pipeline_failures` returns.
+pub const DEFAULT_MAX_STACK_TRACE_CHARS: usize = 2000;
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct GetPipelineFailuresRequest {
+  #[serde(flatten)]
+  pub locator: ProjectLocator,
+  /// Pipeline ID
+  pub pipeline_id: u64,
+  /// Keep at most this many characters of each stack trace (default 2000)
+  #[serde(default)]
+  pub max_stack_trace_chars: Option<usize>,
+  #[serde(flatten)]
+  pub projection: Projection,
+}
+
+impl GetPipelineFailuresRequest {
+  pub fn max_stack_trace_chars(&self) -> usize {
+    self.max_stack_trace_chars.unwrap_or(DEFAULT_MAX_STACK_TRACE_CHARS)
+  }
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum MergeRequestState {
+  Opened,
+  Closed,
+  Merged,
+  Locked,
+  All,
+}
+
+impl MergeRequestState {
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      MergeRequestState::Opened => "opened",
+      MergeRequestState::Closed => "closed",
+      MergeRequestState::Merged => "merged",
+      MergeRequestState::Locked => "locked",
+      MergeRequestState::All => "all",
+    }
+  }
+}
+
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum IssueLinkType {
+  #[default]
+  RelatesTo,
+  Blocks,
+  IsBlockedBy,
+}
+
+impl IssueLinkType {
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      IssueLinkType::RelatesTo => "relates_to",
+      IssueLinkType::Blocks => "blocks",
+      IssueLinkType::IsBlockedBy => "is_blocked_by",
+    }
+  }
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ResourceEventType {
+  Label,
+  State,
+  Milestone,
+}
+
+impl ResourceEventType {
+  /// The GitLab endpoint listing this kind of event.
+  pub fn resource(&self) -> &'static str {
+    match self {
+      ResourceEventType::Label => "resource_label_events",
+      ResourceEventType::State => "resource_state_events",
+      ResourceEventType::Milestone => "resource_milestone_events",
+    }
+  }
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum MergeRequestOrderBy {
+  Id,
+  CreatedAt,
+  UpdatedAt,
+  MergedAt,
+  Title,
+}
+
+impl MergeRequestOrderBy {
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      MergeRequestOrderBy::Id => "id",
+      MergeRequestOrderBy::CreatedAt => "created_at",
+      MergeRequestOrderBy::UpdatedAt => "updated_at",
+      MergeRequestOrderBy::MergedAt => "merged_at",
+      MergeRequestOrderBy::Title => "title",
+    }
+  }
+}
+
+/// Default cap on items collected when `all_pages` is set.
+pub const DEFAULT_MAX_ITEMS: usize = 1000;
+
+/// Default cap with `keyset`, which is meant for walking very large projects.
+pub const DEFAULT_KEYSET_MAX_ITEMS: usize = 50_000;
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ListMergeRequestsRequest {
+  #[serde(flatten)]
+  pub locator: ProjectLocator,
+  /// Filter by state (opened, closed, merged, locked, all)
+  #[serde(default)]
+  pub state: Option<MergeRequestState>,
+  /// Order by id, created_at, updated_at, merged_at, or title
+  #[serde(default)]
+  pub order_by: Option<MergeRequestOrderBy>,
+  /// Sort direction (asc or desc)
+  #[serde(default)]
+  pub sort: Option<SortDirection>,
+  #[serde(flatten)]
+  pub pagination: Pagination,
+  /// Follow every page instead of returning a single one
+  #[serde(default)]
+  pub all_pages: Option<bool>,
+  /// With all_pages, use GitLab keyset (cursor-based) pagination, which
+  /// stays fast on projects with tens of thousands of merge requests.
+  /// order_by must be id (the default) or updated_at
+  #[serde(default)]
+  pub keyset: Option<bool>,
+  /// With all_pages, stop once this many merge requests are collected, at
+  /// the end of a page (default 1000, or 50000 with keyset)
+  #[serde(default)]
+  pub max_items: Option<usize>,
+  /// Return only iid, title, state, author_username, reviewer_usernames,
+  /// web_url, and updated_at for each merge request
+  #[serde(default)]
+  pub compact: Option<bool>,
+  #[serde(flatten)]
+  pub projection: Projection,
+}
+
+impl ListMergeRequestsRequest {
+  fn keyset(&self) -> bool {
+    self.all_pages.unwrap_or(false) && self.keyset.unwrap_or(false)
+  }
+
+  /// Keyset pagination only supports ordering by id or updated_at.
+  pub fn validate(&self) -> Result<(), McpError> {
+    match &self.order_by {
+      Some(MergeRequestOrderBy::Id | MergeRequestOrderBy::UpdatedAt) | None => Ok(()),
+      Some(order_by) if self.keyset() => Err(McpError::invalid_params(
+        format!("keyset pagination needs order_by id or updated_at, not {}", order_by.as_str()),
+        None,
+      )),
+      Some(_) => Ok(()),
+    }
+  }
+
+  pub fn query(&self) -> Vec<(&'static str, String)> {
+    let mut query = Vec::new();
+    if let Some(state) = &self.state {
+      query.push(("state", state.as_str().to_string()));
+    }
+    match &self.order_by {
+      Some(order_by) => query.push(("order_by", order_by.as_str().to_string())),
+      None if self.keyset() => query.push(("order_by", "id".to_string())),
+      None => {}
+    }
+    if let Some(sort) = &self.sort {
+      query.push(("sort", sort.as_str().to_string()));
+    }
+    if !self.all_pages.unwrap_or(false) {
+      if let Some(page) = self.pagination.page() {
+        query.push(("page", page.to_string()));
+      }
+    }
+    if let Some(per_page) = self.pagination.per_page() {
+      query.push(("per_page", per_page.to_string()));
+    }
+    query
+  }
+
+  pub fn page_walk(&self) -> Option<PageWalk> {
+    let keyset = self.keyset();
+    let default_max_items = if keyset { DEFAULT_KEYSET_MAX_ITEMS } else { DEFAULT_MAX_ITEMS };
+    self.all_pages.unwrap_or(false).then(|| PageWalk {
+      keyset,
+      max_items: self.max_items.unwrap_or(default_max_items),
+    })
+  }
+}
+
+#[cfg(feature = "database")]
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct GetAuditLogRequest {
+  /// Number of entries to return (default 50, max 500)
+  #[serde(default)]
+  pub limit: Option<u32>,
+  #[serde(flatten)]
+  pub projection: Projection,
+}
+
+#[cfg(feature = "database")]
+impl GetAuditLogRequest {
+  pub fn limit(&self) -> u32 {
+    self.limit.unwrap_or(50).clamp(1, 500)
+  }
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct GetMergeRequestMergeReadinessRequest {
+  #[serde(flatten)]
+  pub locator: MergeRequestLocator,
+  #[serde(flatten)]
+  pub projection: Projection,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct GetMergeRequestChangesPageRequest {
+  #[serde(flatten)]
+  pub locator: MergeRequestLocator,
+  /// Index of the first file to return, in new_path order (default 0)
+  #[serde(default)]
+  pub offset: Option<usize>,
+  /// Number of files to return (default 10)
+  #[serde(default)]
+  pub limit: Option<usize>,
+  #[serde(flatten)]
+  pub projection: Projection,
+}
+
+impl GetMergeRequestChangesPageRequest {
+  pub fn offset(&self) -> usize {
+    self.offset.unwrap_or(0)
+  }
+
+  pub fn limit(&self) -> usize {
+    self.limit.unwrap_or(10).max(1)
+  }
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct GetMergeRequestDiffRefsRequest {
+  #[serde(flatten)]
+  pub locator: MergeRequestLocator,
+  #[serde(flatten)]
+  pub projection: Projection,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct GetMergeRequestApprovalStateRequest {
+  #[serde(flatten)]
+  pub locator: MergeRequestLocator,
+  #[serde(flatten)]
+  pub projection: Projection,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum JobScope {
+  Created,
+  Pending,
+  Running,
+  Failed,
+  Success,
+  Canceled,
+  Skipped,
+  WaitingForResource,
+  Manual,
+}
+
+impl JobScope {
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      JobScope::Created => "created",
+      JobScope::Pending => "pending",
+      JobScope::Running => "running",
+      JobScope::Failed => "failed",
+      JobScope::Success => "success",
+      JobScope::Canceled => "canceled",
+      JobScope::Skipped => "skipped",
+      JobScope::WaitingForResource => "waiting_for_resource",
+      JobScope::Manual => "manual",
+    }
+  }
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ListProjectJobsRequest {
+  #[serde(flatten)]
+  pub locator: ProjectLocator,
+  /// Only return jobs with one of these statuses (e.g. ["failed"])
+  #[serde(default)]
+  pub scope: Vec<JobScope>,
+  /// Only keep jobs whose name contains this text (case-insensitive),
+  /// applied to the fetched page
+  #[serde(default)]
+  pub name_contains: Option<String>,
+  #[serde(flatten)]
+  pub pagination: Pagination,
+  #[serde(flatten)]
+  pub projection: Projection,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct SummarizeMergeRequestRequest {
+  #[serde(flatten)]
+  pub locator: MergeRequestLocator,
+  #[serde(flatten)]
+  pub projection: Projection,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct GetMergeRequestConflictsRequest {
+  #[serde(flatten)]
+  pub locator: MergeRequestLocator,
+  #[serde(flatten)]
+  pub projection: Projection,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ListMyReviewRequestsRequest {
+  /// Whose review queue to list; defaults to the user the token belongs to
+  #[serde(default)]
+  pub username: Option<String>,
+  #[serde(flatten)]
+  pub pagination: Pagination,
+  /// Return only iid, title, state, author_username, reviewer_usernames,
+  /// web_url, and updated_at for each merge request
+  #[serde(default)]
+  pub compact: Option<bool>,
+  #[serde(flatten)]
+  pub projection: Projection,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TodoState {
+  Pending,
+  Done,
+}
+
+impl TodoState {
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      TodoState::Pending => "pending",
+      TodoState::Done => "done",
+    }
+  }
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TodoType {
+  Issue,
+  MergeRequest,
+  Commit,
+  Epic,
+  Design,
+  Alert,
+}
+
+impl TodoType {
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      TodoType::Issue => "Issue",
+      TodoType::MergeRequest => "MergeRequest",
+      TodoType::Commit => "Commit",
+      TodoType::Epic => "Epic",
+      TodoType::Design => "DesignManagement::Design",
+      TodoType::Alert => "AlertManagement::Alert",
+    }
+  }
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ListTodosRequest {
+  /// Only return to-dos in this state (GitLab default: pending)
+  #[serde(default)]
+  pub state: Option<TodoState>,
+  /// Only return to-dos about this kind of item
+  #[serde(default, rename = "type")]
+  pub todo_type: Option<TodoType>,
+  #[serde(flatten)]
+  pub pagination: Pagination,
+  #[serde(flatten)]
+  pub projection: Projection,
+}
+
+impl ListTodosRequest {
+  pub fn query(&self) -> Vec<(&'static str, String)> {
+    let mut query = Vec::new();
+    if let Some(state) = &self.state {
+      query.push(("state", state.as_str().to_string()));
+    }
+    if let Some(todo_type) = &self.todo_type {
+      query.push(("type", todo_type.as_str().to_string()));
+    }
+    if let Some(page) = self.pagination.page() {
+      query.push(("page", page.to_string()));
+    }
+    if let Some(per_page) = self.pagination.per_page() {
+      query.push(("per_page", per_page.to_string()));
+    }
+    query
+  }
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct MarkTodoDoneRequest {
+  /// ID of the to-do item, from list_todos
+  pub todo_id: u64,
+  #[serde(flatten)]
+  pub write: WriteOptions,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct BatchDiscussion {
+  /// Markdown body of the discussion comment
+  pub body: String,
+  /// Review label such as issue, nitpick, suggestion, question, or praise;
+  /// prepends the configured prefix (e.g. "**issue:**") to the body
+  #[serde(default)]
+  pub severity: Option<String>,
+  /// Position payload for line-specific comments
+  pub position: Value,
+  /// Optionally resolve the discussion immediately
+  #[serde(default)]
+  pub resolve: Option<bool>,
+}
+
+impl BatchDiscussion {
+  pub fn payload(&self) -> Result<Value, McpError> {
+    build_discussion_payload(&self.body, &self.position, self.resolve)
+  }
+}
+
+/// Most discussions one batch call may create.
+pub const MAX_BATCH_DISCUSSIONS: usize = 50;
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct CreateMergeRequestDiscussionsBatchRequest {
+  #[serde(flatten)]
+  pub locator: MergeRequestLocator,
+  /// Discussions to create, each with its own body, position, and resolve flag
+  pub discussions: Vec<BatchDiscussion>,
+  #[serde(flatten)]
+  pub write: WriteOptions,
+}
+
+#[derive(Debug, Default, Clone, Copy, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum DiffSide {
+  /// Line numbers in the new version of the file
+  #[default]
+  New,
+  /// Line numbers in the old version of the file
+  Old,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct QuoteReplyRequest {
+  #[serde(flatten)]
+  pub locator: MergeRequestLocator,
+  /// ID of the discussion to reply to
+  pub discussion_id: String,
+  /// Markdown body of the reply, placed after the quoted code
+  pub body: String,
+  /// Path of the file to quote from
+  pub file_path: String,
+  /// First line to quote
+  pub line: u64,
+  /// Last line to quote (defaults to `line`)
+  #[serde(default)]
+  pub end_line: Option<u64>,
+  /// Whether the line numbers refer to the new (default) or old file
+  #[serde(default)]
+  pub side: DiffSide,
+  /// Set to false to post `body` as a plain reply without quoting
+  #[serde(default)]
+  pub quote: Option<bool>,
+  #[serde(flatten)]
+  pub write: WriteOptions,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct MergeMergeRequestRequest {
+  #[serde(flatten)]
+  pub locator: MergeRequestLocator,
+  /// Custom merge commit message
+  #[serde(default)]
+  pub merge_commit_message: Option<String>,
+  /// Squash the commits into one before merging
+  #[serde(default)]
+  pub squash: Option<bool>,
+  /// Custom squash commit message
+  #[serde(default)]
+  pub squash_commit_message: Option<String>,
+  /// Delete the source branch after merging
+  #[serde(default)]
+  pub should_remove_source_branch: Option<bool>,
+  /// Only merge if the source branch HEAD still has this SHA
+  #[serde(default)]
+  pub sha: Option<String>,
+  /// Refuse to merge while blocking discussions are unresolved
+  #[serde(default)]
+  pub require_resolved: Option<bool>,
+  #[serde(flatten)]
+  pub write: WriteOptions,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct GetRepositoryFileRequest {
+  #[serde(flatten)]
+  pub locator: ProjectLocator,
+  /// Path of the file in the repository (e.g. "src/main.rs")
+  pub file_path: String,
+  /// Branch, tag, or commit SHA (defaults to the project's default branch)
+  #[serde(default, rename = "ref")]
+  pub git_ref: Option<String>,
+  #[serde(flatten)]
+  pub projection: Projection,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct CompareRefsRequest {
+  #[serde(flatten)]
+  pub locator: ProjectLocator,
+  /// Base branch, tag, or commit SHA (defaults to the project's default branch)
+  #[serde(default)]
+  pub from: Option<String>,
+  /// Branch, tag, or commit SHA to compare against `from`
+  pub to: String,
+  /// Compare `from` and `to` directly instead of from their merge base
+  #[serde(default)]
+  pub straight: Option<bool>,
+  #[serde(flatten)]
+  pub projection: Projection,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct GetMergeRequestVersionDiffRequest {
+  #[serde(flatten)]
+  pub locator: MergeRequestLocator,
+  /// Version ID from get_merge_request_versions
+  pub version_id: u64,
+  #[serde(flatten)]
+  pub projection: Projection,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct GetMergeRequestRawDiffRequest {
+  #[serde(flatten)]
+  pub locator: MergeRequestLocator,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct GetJobLogRequest {
+  #[serde(flatten)]
+  pub locator: ProjectLocator,
+  /// CI job ID
+  pub job_id: u64,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct GetMergeRequestDiffsRequest {
+  #[serde(flatten)]
+  pub locator: MergeRequestLocator,
+  #[serde(flatten)]
+  pub pagination: Pagination,
+  #[serde(flatten)]
+  pub projection: Projection,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct GetMergeRequestDiffStatsRequest {
+  #[serde(flatten)]
+  pub locator: MergeRequestLocator,
+  #[serde(flatten)]
+  pub projection: Projection,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct MergeRequestStateRequest {
+  #[serde(flatten)]
+  pub locator: MergeRequestLocator,
+  #[serde(flatten)]
+  pub write: WriteOptions,
+}
+
+/// A merge request state change sent as `state_event`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StateEvent {
+  Close,
+  Reopen,
+}
+
+impl StateEvent {
+  pub fn as_str(self) -> &'static str {
+    match self {
+      StateEvent::Close => "close",
+      StateEvent::Reopen => "reopen",
+    }
+  }
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct SetMergeRequestDraftRequest {
+  #[serde(flatten)]
+  pub locator: MergeRequestLocator,
+  /// true to mark the merge request as draft, false to mark it ready
+  pub draft: bool,
+  #[serde(flatten)]
+  pub write: WriteOptions,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct SetTimeEstimateRequest {
+  #[serde(flatten)]
+  pub locator: MergeRequestLocator,
+  /// Estimate in GitLab's duration format, e.g. "3h30m" or "1w2d"
+  pub duration: String,
+  #[serde(flatten)]
+  pub write: WriteOptions,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct AddSpentTimeRequest {
+  #[serde(flatten)]
+  pub locator: MergeRequestLocator,
+  /// Time spent in GitLab's duration format, e.g. "45m"; prefix with "-" to subtract
+  pub duration: String,
+  /// Optional note on what the time was spent on
+  #[serde(default)]
+  pub summary: Option<String>,
+  #[serde(flatten)]
+  pub write: WriteOptions,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct GetMergeRequestEventsRequest {
+  #[serde(flatten)]
+  pub locator: MergeRequestLocator,
+  /// Which history to list: label, state, or milestone
+  pub event_type: ResourceEventType,
+  #[serde(flatten)]
+  pub projection: Projection,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct MoveIssueRequest {
+  #[serde(flatten)]
+  pub locator: ProjectLocator,
+  /// The issue's IID in the source project
+  pub issue_iid: u64,
+  /// Destination project ID or full path
+  pub to_project: String,
+  #[serde(flatten)]
+  pub write: WriteOptions,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct CreateIssueLinkRequest {
+  #[serde(flatten)]
+  pub locator: ProjectLocator,
+  /// The source issue's IID
+  pub issue_iid: u64,
+  /// Project of the target issue, ID or full path (defaults to the source project)
+  #[serde(default)]
+  pub target_project: Option<String>,
+  /// The target issue's IID
+  pub target_issue_iid: u64,
+  /// relates_to (default), blocks, or is_blocked_by, read as "source <link_type> target"
+  #[serde(default)]
+  pub link_type: IssueLinkType,
+  #[serde(flatten)]
+  pub write: WriteOptions,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct DeleteIssueLinkRequest {
+  #[serde(flatten)]
+  pub locator: ProjectLocator,
+  /// The source issue's IID
+  pub issue_iid: u64,
+  /// ID of the link (issue_link_id from the issue's links)
+  pub issue_link_id: u64,
+  #[serde(flatten)]
+  pub write: WriteOptions,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct GetReviewBacklogRequest {
+  #[serde(flatten)]
+  pub locator: ProjectLocator,
+  /// Open merge requests not updated for this many days count as stale (default 7)
+  #[serde(default)]
+  pub stale_days: Option<u64>,
+  /// Look up each merge request's latest pipeline to count failures (default true)
+  #[serde(default)]
+  pub check_pipelines: Option<bool>,
+  /// Stop after this many open merge requests (default 1000)
+  #[serde(default)]
+  pub max_items: Option<usize>,
+  #[serde(flatten)]
+  pub projection: Projection,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ListMergeRequestDiscussionsRequest {
+  #[serde(flatten)]
+  pub locator: MergeRequestLocator,
+  /// Only return discussions with at least one unresolved note
+  #[serde(default)]
+  pub unresolved_only: Option<bool>,
+  #[serde(flatten)]
+  pub projection: Projection,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, JsonSchema)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum RestMethod {
+  Get,
+  Post,
+  Put,
+  Delete,
+}
+
+impl RestMethod {
+  pub fn as_method(self) -> reqwest::Method {
+    match self {
+      RestMethod::Get => reqwest::Method::GET,
+      RestMethod::Post => reqwest::Method::POST,
+      RestMethod::Put => reqwest::Method::PUT,
+      RestMethod::Delete => reqwest::Method::DELETE,
+    }
+  }
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct GitLabRestRequest {
+  /// HTTP method: GET, POST, PUT, or DELETE
+  pub method: RestMethod,
+  /// Path relative to /api/v4, e.g. "projects/123/merge_requests/4/award_emoji"
+  pub path: String,
+  /// Query string parameters
+  #[serde(default)]
+  pub query: Option<BTreeMap<String, String>>,
+  /// JSON request body
+  #[serde(default)]
+  pub body: Option<Value>,
+  #[serde(flatten)]
+  pub write: WriteOptions,
+}
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum DiscussionPositionType {
+  Text,
+  Image,
+}
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum DiscussionLinePositionType {
+  New,
+  Old,
+}
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct DiscussionLineReference {
+  pub line_code: String,
+  #[serde(rename = "type")]
+  pub position_type: DiscussionLinePositionType,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub old_line: Option<u32>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub new_line: Option<u32>,
+}
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct DiscussionLineRange {
+  pub start: DiscussionLineReference,
+  pub end: DiscussionLineReference,
+}
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct DiscussionPosition {
+  pub base_sha: String,
+  pub head_sha: String,
+  pub start_sha: String,
+  #[serde(default = "default_position_type")]
+  pub position_type: DiscussionPositionType,
+  pub new_path: String,
+  pub old_path: String,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub new_line: Option<u32>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub old_line: Option<u32>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub line_range: Option<DiscussionLineRange>,
+}
+
+fn default_position_type() -> DiscussionPositionType {
+  DiscussionPositionType::Text
+}
+
+impl DiscussionPosition {
+  pub fn validate(&self) -> Result<(), McpError> {
+    if self.base_sha.trim().is_empty()
+      || self.head_sha.trim().is_empty()
+      || self.start_sha.trim().is_empty()
+    {
+      return Err(McpError::invalid_params(
+        "GitLab discussion position requires base_sha, head_sha, and start_sha",
+        None,
+      ));
+    }
+
+    if self.new_path.trim().is_empty() || self.old_path.trim().is_empty() {
+      return Err(McpError::invalid_params(
+        "GitLab discussion position requires both new_path and old_path",
+        None,
+      ));
+    }
+
+    let has_line = self.new_line.is_some() || self.old_line.is_some() || self.line_range.is_some();
+    if !has_line {
+      return Err(McpError::invalid_params(
+        "GitLab discussion position requires at least one of new_line, old_line, or line_range",
+        None,
+      ));
+    }
+
+    Ok(())
+  }
+}
+
+fn parse_discussion_position(raw: &Value) -> Result<DiscussionPosition, McpError> {
+  let value = match raw {
+    Value::String(s) => serde_json::from_str::<Value>(s).map_err(|err| {
+      McpError::invalid_params("position string is not valid JSON", Some(Value::String(err.to_string())))
+    })?,
+    other => other.clone(),
+  };
+
+  serde_json::from_value::<DiscussionPosition>(value).map_err(|err| {
+    McpError::invalid_params(
+      "position must be a GitLab discussion position object",
+      Some(Value::String(err.to_string())),
+    )
+  })
+}
+
+pub fn map_to_payload(map: Map<String, Value>) -> Value {
+  Value::Object(map)
+}
+
+pub fn discussion_payload(req: &CreateMergeRequestDiscussionRequest) -> Result<Value, McpError> {
+  build_discussion_payload(&req.body, &req.position, req.resolve)
+}
+
+fn build_discussion_payload(body: &str, position: &Value, resolve: Option<bool>) -> Result<Value, McpError> {
+  let position = parse_discussion_position(position)?;
+  position.validate()?;
+
+  let mut map = Map::new();
+  map.insert("body".to_string(), Value::String(body.to_string()));
+  let position = serde_json::to_value(&position).map_err(|err| {
+    McpError::internal_error(
+      "Failed to serialize GitLab discussion position",
+      Some(Value::String(err.to_string())),
+    )
   })?;
   map.insert("position".to_string(), position);
-  if let Some(resolve) = req.resolve {
+  if let Some(resolve) = resolve {
     map.insert("resolve".to_string(), Value::Bool(resolve));
   }
   Ok(map_to_payload(map))
 }
 
-pub fn note_payload(req: &CreateMergeRequestNoteRequest) -> Value {
+/// Prepends the prefix configured for `severity` to `body`. The body is left
+/// as is without a severity; an unknown severity is rejected.
+pub fn apply_severity(body: &mut String, severity: Option<&str>, review: &ReviewConfig) -> Result<(), McpError> {
+  let Some(severity) = severity.filter(|severity| !severity.trim().is_empty()) else {
+    return Ok(());
+  };
+  let prefix = review.severity_prefix(severity).ok_or_else(|| {
+    McpError::invalid_params(
+      format!("Unknown severity '{}'; expected one of: {}", severity, review.severities().join(", ")),
+      None,
+    )
+  })?;
+  *body = format!("{} {}", prefix, body);
+  Ok(())
+}
+
+pub fn note_payload(req: &CreateMergeRequestNoteRequest) -> Value {
+  let mut map = Map::new();
+  map.insert("body".to_string(), Value::String(req.body.clone()));
+  if let Some(confidential) = req.confidential {
+    map.insert("confidential".to_string(), Value::Bool(confidential));
+  }
+  if let Some(commit_id) = req.commit_id.as_deref().map(str::trim).filter(|commit_id| !commit_id.is_empty()) {
+    map.insert("commit_id".to_string(), Value::String(commit_id.to_string()));
+  }
+  map_to_payload(map)
+}
+
+/// Upper bound for a single text block in diff/report tool results.
+pub const MAX_CONTENT_CHUNK_BYTES: usize = 64 * 1024;
+
+fn format_json(value: &Value) -> Result<String, McpError> {
+  serde_json::to_string_pretty(value).map_err(|err| McpError::internal_error(
+    "Failed to format GitLab response",
+    Some(Value::String(err.to_string())),
+  ))
+}
+
+/// A result holding `value` as pretty-printed text, plus the value itself as
+/// structured content. The server drops the structured copy unless
+/// `server.structured_output` is on.
+pub fn json_result(value: Value) -> Result<CallToolResult, McpError> {
+  let mut result = CallToolResult::success(vec![Content::text(format_json(&value)?)]);
+  result.structured_content = Some(structured_content(value));
+  Ok(result)
+}
+
+/// Like `json_result`, but splits the serialized output into several text
+/// contents of at most `max_bytes` each. Splits prefer line breaks and never
+/// fall inside a UTF-8 sequence.
+pub fn json_result_chunked(value: Value, max_bytes: usize) -> Result<CallToolResult, McpError> {
+  let mut result = text_result_chunked(&format_json(&value)?, max_bytes);
+  result.structured_content = Some(structured_content(value));
+  Ok(result)
+}
+
+/// MCP requires structured content to be an object, so any other value is
+/// wrapped as `{"result": value}`.
+fn structured_content(value: Value) -> Value {
+  match value {
+    Value::Object(_) => value,
+    other => serde_json::json!({ "result": other }),
+  }
+}
+
+/// Bytes of text a tool result sends to the client.
+pub fn result_size(result: &CallToolResult) -> usize {
+  result
+    .content
+    .iter()
+    .filter_map(|content| content.as_text())
+    .map(|text| text.text.len())
+    .sum()
+}
+
+/// Cuts a result's text contents down to `max_bytes` in total and appends a
+/// `{ "truncated": true, "original_size": N, "max_response_bytes": M }`
+/// marker. Structured content is dropped, since it would no longer match.
+pub fn truncate_result(mut result: CallToolResult, max_bytes: usize) -> CallToolResult {
+  let original_size = result_size(&result);
+  let mut remaining = max_bytes;
+  let mut contents = Vec::new();
+  for content in result.content {
+    let Some(text) = content.as_text().map(|text| text.text.as_str()) else {
+      contents.push(content);
+      continue;
+    };
+    if remaining == 0 {
+      break;
+    }
+    if text.len() <= remaining {
+      remaining -= text.len();
+      contents.push(content);
+      continue;
+    }
+    let mut end = remaining;
+    while !text.is_char_boundary(end) {
+      end -= 1;
+    }
+    contents.push(Content::text(&text[..end]));
+    remaining = 0;
+  }
+  let marker = serde_json::json!({
+    "truncated": true,
+    "original_size": original_size,
+    "max_response_bytes": max_bytes,
+  });
+  contents.push(Content::text(marker.to_string()));
+  result.content = contents;
+  result.structured_content = None;
+  result
+}
+
+/// Splits plain text into contents of at most `max_bytes` each, the same way
+/// `json_result_chunked` does.
+pub fn text_result_chunked(text: &str, max_bytes: usize) -> CallToolResult {
+  let max_bytes = max_bytes.max(4);
+  let mut contents = Vec::new();
+  let mut rest = text;
+  while rest.len() > max_bytes {
+    let mut end = max_bytes;
+    while !rest.is_char_boundary(end) {
+      end -= 1;
+    }
+    if let Some(newline) = rest[..end].rfind('\n') {
+      if newline > 0 {
+        end = newline + 1;
+      }
+    }
+    let (chunk, tail) = rest.split_at(end);
+    contents.push(Content::text(chunk));
+    rest = tail;
+  }
+  contents.push(Content::text(rest));
+  CallToolResult::success(contents)
+}
+
+pub fn release_payload(req: &CreateReleaseRequest) -> Result<Value, McpError> {
+  if req.tag_name.trim().is_empty() {
+    return Err(McpError::invalid_params("tag_name must not be empty", None));
+  }
+
+  let mut map = Map::new();
+  map.insert("tag_name".to_string(), Value::String(req.tag_name.clone()));
+  map.insert("name".to_string(), Value::String(req.name.clone()));
+  map.insert("description".to_string(), Value::String(req.description.clone()));
+  if let Some(git_ref) = &req.git_ref {
+    map.insert("ref".to_string(), Value::String(git_ref.clone()));
+  }
+  if let Some(milestones) = &req.milestones {
+    map.insert(
+      "milestones".to_string(),
+      Value::Array(milestones.iter().cloned().map(Value::String).collect()),
+    );
+  }
+  Ok(map_to_payload(map))
+}
+
+/// Payload for `create_tag`; `git_ref` is the request's ref, or the default
+/// branch when the request omitted it.
+pub fn tag_payload(req: &CreateTagRequest, git_ref: &str) -> Result<Value, McpError> {
+  if req.tag_name.trim().is_empty() {
+    return Err(McpError::invalid_params("tag_name must not be empty", None));
+  }
+
+  let mut map = Map::new();
+  map.insert("tag_name".to_string(), Value::String(req.tag_name.clone()));
+  map.insert("ref".to_string(), Value::String(git_ref.to_string()));
+  if let Some(message) = &req.message {
+    map.insert("message".to_string(), Value::String(message.clone()));
+  }
+  Ok(map_to_payload(map))
+}
+
+pub fn reviewer_ids(merge_request: &Value) -> Vec<u64> {
+  merge_request
+    .get("reviewers")
+    .and_then(Value::as_array)
+    .map(|reviewers| {
+      reviewers
+        .iter()
+        .filter_map(|reviewer| reviewer.get("id").and_then(Value::as_u64))
+        .collect()
+    })
+    .unwrap_or_default()
+}
+
+pub fn reviewers_payload(reviewer_ids: &[u64]) -> Value {
+  let mut map = Map::new();
+  map.insert(
+    "reviewer_ids".to_string(),
+    Value::Array(reviewer_ids.iter().map(|id| Value::from(*id)).collect()),
+  );
+  map_to_payload(map)
+}
+
+pub fn reviewers_result(merge_request: &Value) -> Value {
+  if is_dry_run_response(merge_request) {
+    return merge_request.clone();
+  }
+  let mut map = Map::new();
+  for key in ["iid", "web_url", "reviewers"] {
+    map.insert(key.to_string(), merge_request.get(key).cloned().unwrap_or(Value::Null));
+  }
+  map_to_payload(map)
+}
+
+/// Adds a `web_url` linking to the created note on the merge request page.
+/// Notes carry their own `id`; discussions link to their first note.
+pub fn with_note_web_url(mut created: Value, merge_request: &Value) -> Value {
+  let note = created
+    .get("notes")
+    .and_then(|notes| notes.get(0))
+    .unwrap_or(&created);
+  let note_id = note.get("id").and_then(Value::as_u64);
+  let merge_request_url = merge_request.get("web_url").and_then(Value::as_str);
+  if let (Some(note_id), Some(url), Some(map)) = (note_id, merge_request_url, created.as_object_mut()) {
+    map.insert("web_url".to_string(), Value::String(format!("{}#note_{}", url, note_id)));
+  }
+  created
+}
+
+pub fn commit_onto_branch_payload(req: &CommitOntoBranchRequest) -> Result<Value, McpError> {
+  if req.sha.trim().is_empty() || req.branch.trim().is_empty() {
+    return Err(McpError::invalid_params("sha and branch must not be empty", None));
+  }
+
+  let mut map = Map::new();
+  map.insert("branch".to_string(), Value::String(req.branch.clone()));
+  Ok(map_to_payload(map))
+}
+
+pub fn commit_payload(req: &CreateCommitRequest) -> Result<Value, McpError> {
+  if req.branch.trim().is_empty() || req.commit_message.trim().is_empty() {
+    return Err(McpError::invalid_params(
+      "create_commit requires a branch and a commit_message",
+      None,
+    ));
+  }
+  if req.actions.is_empty() {
+    return Err(McpError::invalid_params("create_commit requires at least one action", None));
+  }
+  for action in &req.actions {
+    if action.file_path.trim().is_empty() {
+      return Err(McpError::invalid_params("Every commit action requires a file_path", None));
+    }
+    let needs_content = matches!(action.action, CommitActionType::Create | CommitActionType::Update);
+    if needs_content && action.content.is_none() {
+      return Err(McpError::invalid_params(
+        format!("Commit action for {} requires content", action.file_path),
+        None,
+      ));
+    }
+    if action.action == CommitActionType::Move && action.previous_path.is_none() {
+      return Err(McpError::invalid_params(
+        format!("Move action for {} requires previous_path", action.file_path),
+        None,
+      ));
+    }
+  }
+
   let mut map = Map::new();
-  map.insert("body".to_string(), Value::String(req.body.clone()));
-  if let Some(confidential) = req.confidential {
-    map.insert("confidential".to_string(), Value::Bool(confidential));
+  map.insert("branch".to_string(), Value::String(req.branch.clone()));
+  map.insert("commit_message".to_string(), Value::String(req.commit_message.clone()));
+  if let Some(start_branch) = &req.start_branch {
+    map.insert("start_branch".to_string(), Value::String(start_branch.clone()));
+  }
+  let actions = serde_json::to_value(&req.actions).map_err(|err| {
+    McpError::internal_error(
+      "Failed to serialize GitLab commit actions",
+      Some(Value::String(err.to_string())),
+    )
+  })?;
+  map.insert("actions".to_string(), actions);
+  Ok(map_to_payload(map))
+}
+
+pub fn apply_suggestion_payload(req: &ApplySuggestionRequest) -> Value {
+  let mut map = Map::new();
+  if let Some(commit_message) = &req.commit_message {
+    map.insert("commit_message".to_string(), Value::String(commit_message.clone()));
   }
   map_to_payload(map)
 }
 
-pub fn json_result(value: Value) -> Result<CallToolResult, McpError> {
-  serde_json::to_string_pretty(&value)
-    .map(|text| CallToolResult::success(vec![Content::text(text)]))
-    .map_err(|err| McpError::internal_error(
-      "Failed to format GitLab response",
-      Some(Value::String(err.to_string())),
-    ))
+fn is_failing_test_case(test_case: &Value) -> bool {
+  matches!(
+    test_case.get("status").and_then(Value::as_str),
+    Some("failed") | Some("error")
+  )
+}
+
+/// Prune a pipeline test report down to failing suites and test cases.
+pub fn failed_test_report(mut report: Value) -> Value {
+  if let Some(suites) = report.get_mut("test_suites").and_then(Value::as_array_mut) {
+    suites.retain_mut(|suite| {
+      let Some(cases) = suite.get_mut("test_cases").and_then(Value::as_array_mut) else {
+        return false;
+      };
+      cases.retain(is_failing_test_case);
+      !cases.is_empty()
+    });
+  }
+  report
+}
+
+/// The failing test cases of a pipeline test report, sorted by suite, as
+/// `{suite, name, classname, stack_trace}` with each stack trace cut to
+/// `max_stack_trace_chars`. `status` is `passing` when nothing failed.
+pub fn pipeline_failures(report: &Value, max_stack_trace_chars: usize) -> Value {
+  let mut failures: Vec<(&str, Value)> = Vec::new();
+  for suite in report["test_suites"].as_array().into_iter().flatten() {
+    let suite_name = suite["name"].as_str().unwrap_or_default();
+    for case in suite["test_cases"].as_array().into_iter().flatten() {
+      if !is_failing_test_case(case) {
+        continue;
+      }
+      let stack_trace = case["stack_trace"]
+        .as_str()
+        .map(|trace| Value::String(truncate_chars(trace, max_stack_trace_chars)))
+        .unwrap_or(Value::Null);
+      failures.push((suite_name, serde_json::json!({
+        "suite": suite_name,
+        "name": case["name"],
+        "classname": case["classname"],
+        "stack_trace": stack_trace,
+      })));
+    }
+  }
+  failures.sort_by_key(|(suite, _)| *suite);
+  let status = if failures.is_empty() { "passing" } else { "failing" };
+
+  serde_json::json!({
+    "status": status,
+    "failures": failures.into_iter().map(|(_, failure)| failure).collect::<Vec<_>>(),
+  })
+}
+
+/// `text` cut to at most `max_chars` characters, noting how many were dropped.
+fn truncate_chars(text: &str, max_chars: usize) -> String {
+  match text.char_indices().nth(max_chars) {
+    Some((end, _)) => format!(
+      "{}\n... ({} more characters)",
+      &text[..end],
+      text[end..].chars().count()
+    ),
+    None => text.to_string(),
+  }
+}
+
+/// Keep only the entries of a changes response whose `new_path` or
+/// `old_path` matches one of `patterns`.
+pub fn filter_changes(mut value: Value, patterns: &[String]) -> Value {
+  if let Some(changes) = value.get_mut("changes").and_then(Value::as_array_mut) {
+    changes.retain(|change| {
+      ["new_path", "old_path"].iter().any(|key| {
+        change
+          .get(key)
+          .and_then(Value::as_str)
+          .is_some_and(|path| patterns.iter().any(|pattern| glob_match(pattern, path)))
+      })
+    });
+  }
+  value
+}
+
+/// Replace the `diff` of the largest files in a changes response with a
+/// `{ "truncated": true, "original_size": N }` marker until the serialized
+/// response fits in `max_bytes`. The file list itself is always kept.
+pub fn truncate_changes(mut value: Value, max_bytes: usize) -> Value {
+  let mut size = serde_json::to_string_pretty(&value).map(|text| text.len()).unwrap_or(0);
+  if size <= max_bytes {
+    return value;
+  }
+  let Some(changes) = value.get_mut("changes").and_then(Value::as_array_mut) else {
+    return value;
+  };
+
+  let mut by_size: Vec<(usize, usize)> = changes
+    .iter()
+    .enumerate()
+    .filter_map(|(index, change)| {
+      change.get("diff").and_then(Value::as_str).map(|diff| (index, diff.len()))
+    })
+    .collect();
+  by_size.sort_by_key(|(_, size)| std::cmp::Reverse(*size));
+
+  for (index, original_size) in by_size {
+    if size <= max_bytes {
+      break;
+    }
+    let marker = serde_json::json!({ "truncated": true, "original_size": original_size });
+    let removed = serde_json::to_string(&changes[index]["diff"]).map(|text| text.len()).unwrap_or(0);
+    let added = serde_json::to_string(&marker).map(|text| text.len()).unwrap_or(0);
+    changes[index]["diff"] = marker;
+    size = size.saturating_sub(removed).saturating_add(added);
+  }
+  value
+}
+
+/// Decides whether a merge request can be merged right now. `approvals` is
+/// `None` when the approvals endpoint is unavailable, and `pipeline` is
+/// `None` when no pipeline has run.
+pub fn merge_readiness(merge_request: &Value, approvals: Option<&Value>, pipeline: Option<&Value>) -> Value {
+  let mut blockers = Vec::new();
+
+  let state = merge_request.get("state").and_then(Value::as_str).unwrap_or("unknown");
+  if state != "opened" {
+    blockers.push(format!("merge request is {}", state));
+  }
+  let is_draft = ["draft", "work_in_progress"]
+    .iter()
+    .any(|key| merge_request.get(key).and_then(Value::as_bool).unwrap_or(false));
+  if is_draft {
+    blockers.push("draft".to_string());
+  }
+  if merge_request.get("has_conflicts").and_then(Value::as_bool).unwrap_or(false) {
+    blockers.push("has merge conflicts".to_string());
+  }
+  if merge_request.get("blocking_discussions_resolved").and_then(Value::as_bool) == Some(false) {
+    blockers.push("unresolved discussions".to_string());
+  }
+
+  let approvals_left = approvals
+    .and_then(|approvals| approvals.get("approvals_left"))
+    .and_then(Value::as_u64)
+    .unwrap_or(0);
+  if approvals_left > 0 {
+    let noun = if approvals_left == 1 { "approval" } else { "approvals" };
+    blockers.push(format!("{} {} left", approvals_left, noun));
+  }
+
+  let pipeline_status = pipeline
+    .and_then(|pipeline| pipeline.get("status"))
+    .and_then(Value::as_str);
+  match pipeline_status {
+    None | Some("success") | Some("skipped") | Some("manual") => {}
+    Some("failed") => blockers.push("pipeline failed".to_string()),
+    Some(status) => blockers.push(format!("pipeline {}", status)),
+  }
+
+  let approved_by: Vec<Value> = approvals
+    .and_then(|approvals| approvals.get("approved_by"))
+    .and_then(Value::as_array)
+    .map(|approved_by| {
+      approved_by
+        .iter()
+        .filter_map(|entry| entry.pointer("/user/username").cloned())
+        .collect()
+    })
+    .unwrap_or_default();
+  let pick = |value: &Value, keys: &[&str]| {
+    let mut map = Map::new();
+    for key in keys {
+      map.insert(key.to_string(), value.get(key).cloned().unwrap_or(Value::Null));
+    }
+    Value::Object(map)
+  };
+
+  serde_json::json!({
+    "mergeable_now": blockers.is_empty(),
+    "blockers": blockers,
+    "merge_request": pick(merge_request, &["iid", "title", "web_url", "state", "draft", "detailed_merge_status"]),
+    "approvals": approvals.map(|approvals| {
+      let mut summary = pick(approvals, &["approvals_required", "approvals_left"]);
+      summary["approved_by"] = Value::Array(approved_by);
+      summary
+    }),
+    "pipeline": pipeline.map(|pipeline| pick(pipeline, &["id", "status", "ref", "web_url"])),
+  })
+}
+
+/// One slice of a changes response: files sorted by `new_path` so repeated
+/// calls page through them in a stable order.
+pub fn changes_page(mut value: Value, offset: usize, limit: usize) -> Value {
+  let mut changes = match value.get_mut("changes").map(Value::take) {
+    Some(Value::Array(changes)) => changes,
+    _ => Vec::new(),
+  };
+  changes.sort_by_key(|change| change.get("new_path").and_then(Value::as_str).unwrap_or("").to_string());
+  let total_files = changes.len();
+  let page: Vec<Value> = changes.into_iter().skip(offset).take(limit).collect();
+  let next_offset = (offset + page.len() < total_files).then_some(offset + page.len());
+
+  serde_json::json!({
+    "changes": page,
+    "offset": offset,
+    "total_files": total_files,
+    "next_offset": next_offset,
+  })
+}
+
+/// The merge request's `diff_refs`, if GitLab has computed them.
+pub fn diff_refs(merge_request: &Value) -> Option<Value> {
+  merge_request
+    .get("diff_refs")
+    .filter(|refs| refs.get("head_sha").is_some_and(|sha| !sha.is_null()))
+    .cloned()
+}
+
+/// Builds `diff_refs` from the newest entry of a versions response.
+pub fn diff_refs_from_versions(versions: &Value) -> Option<Value> {
+  let latest = versions.get(0)?;
+  Some(serde_json::json!({
+    "base_sha": latest.get("base_commit_sha").cloned().unwrap_or(Value::Null),
+    "head_sha": latest.get("head_commit_sha").cloned().unwrap_or(Value::Null),
+    "start_sha": latest.get("start_commit_sha").cloned().unwrap_or(Value::Null),
+  }))
+}
+
+/// One line of a unified diff. Added lines have no `old_line` and removed
+/// lines have no `new_line`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DiffLine<'a> {
+  pub old_line: Option<u64>,
+  pub new_line: Option<u64>,
+  /// The line including its leading `+`, `-`, or space
+  pub text: &'a str,
+}
+
+/// Every line a unified diff shows, with its old and new line numbers.
+/// Anything before the first hunk header, such as `---`/`+++` file
+/// headers, is skipped.
+pub fn diff_lines(diff: &str) -> Vec<DiffLine<'_>> {
+  let mut lines = Vec::new();
+  let (mut old, mut new) = (0u64, 0u64);
+  let mut in_hunk = false;
+  for text in diff.lines() {
+    if let Some(header) = text.strip_prefix("@@ ") {
+      let mut ranges = header.split_whitespace();
+      let start = |range: Option<&str>, sign: char| {
+        range
+          .and_then(|range| range.strip_prefix(sign))
+          .and_then(|range| range.split(',').next())
+          .and_then(|start| start.parse().ok())
+          .unwrap_or(0)
+      };
+      old = start(ranges.next(), '-');
+      new = start(ranges.next(), '+');
+      in_hunk = true;
+      continue;
+    }
+    if !in_hunk {
+      continue;
+    }
+    let (old_line, new_line) = match text.chars().next() {
+      Some('+') => (None, Some(new)),
+      Some('-') => (Some(old), None),
+      Some(' ') | None => (Some(old), Some(new)),
+      _ => continue,
+    };
+    old += u64::from(old_line.is_some());
+    new += u64::from(new_line.is_some());
+    lines.push(DiffLine { old_line, new_line, text });
+  }
+  lines
+}
+
+/// The diff of the file whose `new_path` or `old_path` is `path`.
+fn file_diff<'a>(changes: &'a Value, path: &str) -> Option<&'a str> {
+  changes
+    .get("changes")
+    .and_then(Value::as_array)?
+    .iter()
+    .find(|change| {
+      ["new_path", "old_path"]
+        .iter()
+        .any(|key| change.get(key).and_then(Value::as_str) == Some(path))
+    })
+    .map(|change| change.get("diff").and_then(Value::as_str).unwrap_or_default())
+}
+
+/// Rejects a discussion position whose line is not shown in the merge
+/// request's diff, which GitLab would otherwise refuse with a vague error.
+pub fn validate_position_in_changes(position: &Value, changes: &Value) -> Result<(), McpError> {
+  let text = |key: &str| position.get(key).and_then(Value::as_str);
+  let number = |key: &str| position.get(key).and_then(Value::as_u64);
+  let (new_path, old_path) = (text("new_path"), text("old_path"));
+  let (new_line, old_line) = (number("new_line"), number("old_line"));
+  if new_line.is_none() && old_line.is_none() {
+    return Ok(());
+  }
+  let path = new_path.or(old_path).unwrap_or_default();
+
+  let change = changes
+    .get("changes")
+    .and_then(Value::as_array)
+    .and_then(|changes| {
+      changes.iter().find(|change| {
+        (new_path.is_some() && change.get("new_path").and_then(Value::as_str) == new_path)
+          || (old_path.is_some() && change.get("old_path").and_then(Value::as_str) == old_path)
+      })
+    })
+    .ok_or_else(|| {
+      McpError::invalid_params(format!("{} is not changed in this merge request", path), None)
+    })?;
+  let diff = change.get("diff").and_then(Value::as_str).unwrap_or_default();
+  // GitLab leaves too_large and collapsed diffs empty, as does max_bytes
+  // truncation; there is nothing to check the line against.
+  if diff.trim().is_empty() {
+    return Ok(());
+  }
+
+  let found = diff_lines(diff).into_iter().any(|line| match (old_line, new_line) {
+    (Some(old_line), Some(new_line)) => line.old_line == Some(old_line) && line.new_line == Some(new_line),
+    (None, Some(new_line)) => line.new_line == Some(new_line),
+    (Some(old_line), None) => line.old_line == Some(old_line),
+    (None, None) => true,
+  });
+  if found {
+    return Ok(());
+  }
+  let line = new_line.or(old_line).unwrap_or_default();
+  Err(McpError::invalid_params(
+    format!("line {} of {} is not part of the diff", line, path),
+    Some(position.clone()),
+  ))
+}
+
+/// One entry of a batch result: `{index, ok: true, result}` or
+/// `{index, ok: false, error: {code, message}}`.
+pub fn batch_item_result(index: usize, result: Result<Value, McpError>) -> Value {
+  match result {
+    Ok(value) => serde_json::json!({ "index": index, "ok": true, "result": value }),
+    Err(err) => serde_json::json!({
+      "index": index,
+      "ok": false,
+      "error": { "code": err.code.0, "message": err.message },
+    }),
+  }
+}
+
+/// Builds a reply body that quotes lines `line..=end_line` of `file_path`
+/// from a changes response as a `diff` code block, followed by `body`.
+pub fn quote_reply_body(
+  changes: &Value,
+  file_path: &str,
+  side: DiffSide,
+  line: u64,
+  end_line: u64,
+  body: &str,
+) -> Result<String, McpError> {
+  let diff = file_diff(changes, file_path).ok_or_else(|| {
+    McpError::invalid_params(format!("{} is not changed in this merge request", file_path), None)
+  })?;
+  let quoted: Vec<&str> = diff_lines(diff)
+    .into_iter()
+    .filter(|diff_line| {
+      let number = match side {
+        DiffSide::New => diff_line.new_line,
+        DiffSide::Old => diff_line.old_line,
+      };
+      number.is_some_and(|number| (line..=end_line).contains(&number))
+    })
+    .map(|diff_line| diff_line.text)
+    .collect();
+  if quoted.is_empty() {
+    return Err(McpError::invalid_params(
+      format!("lines {}-{} of {} are not part of the diff", line, end_line, file_path),
+      None,
+    ));
+  }
+
+  let code = quoted.join("\n");
+  let fence = if code.contains("```") { "````" } else { "```" };
+  let range = if end_line > line { format!("lines {}-{}", line, end_line) } else { format!("line {}", line) };
+  Ok(format!("`{}` {}:\n\n{}diff\n{}\n{}\n\n{}", file_path, range, fence, code, fence, body))
+}
+
+pub fn merge_payload(req: &MergeMergeRequestRequest) -> Value {
+  let mut map = Map::new();
+  for (key, value) in [
+    ("merge_commit_message", &req.merge_commit_message),
+    ("squash_commit_message", &req.squash_commit_message),
+    ("sha", &req.sha),
+  ] {
+    if let Some(value) = value {
+      map.insert(key.to_string(), Value::String(value.clone()));
+    }
+  }
+  if let Some(squash) = req.squash {
+    map.insert("squash".to_string(), Value::Bool(squash));
+  }
+  if let Some(remove) = req.should_remove_source_branch {
+    map.insert("should_remove_source_branch".to_string(), Value::Bool(remove));
+  }
+  map_to_payload(map)
+}
+
+/// IDs of discussions that still have a resolvable, unresolved note.
+pub fn unresolved_discussion_ids(discussions: &Value) -> Vec<String> {
+  discussions
+    .as_array()
+    .map(|discussions| {
+      discussions
+        .iter()
+        .filter(|discussion| is_unresolved(discussion))
+        .filter_map(|discussion| discussion.get("id").and_then(Value::as_str).map(str::to_string))
+        .collect()
+    })
+    .unwrap_or_default()
+}
+
+/// Whether a discussion has at least one resolvable note that is not
+/// resolved yet.
+fn is_unresolved(discussion: &Value) -> bool {
+  discussion
+    .get("notes")
+    .and_then(Value::as_array)
+    .is_some_and(|notes| {
+      notes.iter().any(|note| {
+        note.get("resolvable").and_then(Value::as_bool) == Some(true)
+          && note.get("resolved").and_then(Value::as_bool) == Some(false)
+      })
+    })
+}
+
+/// Keeps only the discussions with an unresolved note.
+pub fn unresolved_discussions(discussions: Value) -> Value {
+  match discussions {
+    Value::Array(items) => Value::Array(items.into_iter().filter(is_unresolved).collect()),
+    other => other,
+  }
+}
+
+/// Replaces a repository file response's base64 `content` with the decoded
+/// text when the file is valid UTF-8. Binary files are left encoded.
+pub fn decode_file_content(mut file: Value) -> Value {
+  use base64::Engine as _;
+
+  let decoded = file
+    .get("content")
+    .and_then(Value::as_str)
+    .filter(|_| file.get("encoding").and_then(Value::as_str) == Some("base64"))
+    .and_then(|content| base64::engine::general_purpose::STANDARD.decode(content).ok())
+    .and_then(|bytes| String::from_utf8(bytes).ok());
+  if let (Some(text), Some(map)) = (decoded, file.as_object_mut()) {
+    map.insert("content".to_string(), Value::String(text));
+    map.insert("encoding".to_string(), Value::String("text".to_string()));
+  }
+  file
+}
+
+/// Projects a merge request down to the few fields needed to pick one out of
+/// a list; `get_merge_request` has the rest.
+pub fn compact_merge_request(merge_request: &Value) -> Value {
+  let reviewer_usernames: Vec<Value> = merge_request
+    .get("reviewers")
+    .and_then(Value::as_array)
+    .map(|reviewers| {
+      reviewers
+        .iter()
+        .filter_map(|reviewer| reviewer.get("username").cloned())
+        .collect()
+    })
+    .unwrap_or_default();
+
+  serde_json::json!({
+    "iid": merge_request.get("iid").cloned().unwrap_or(Value::Null),
+    "title": merge_request.get("title").cloned().unwrap_or(Value::Null),
+    "state": merge_request.get("state").cloned().unwrap_or(Value::Null),
+    "author_username": merge_request.pointer("/author/username").cloned().unwrap_or(Value::Null),
+    "reviewer_usernames": reviewer_usernames,
+    "web_url": merge_request.get("web_url").cloned().unwrap_or(Value::Null),
+    "updated_at": merge_request.get("updated_at").cloned().unwrap_or(Value::Null),
+  })
+}
+
+/// Applies `compact_merge_request` to every merge request in a list.
+pub fn compact_merge_requests(merge_requests: Value) -> Value {
+  match merge_requests {
+    Value::Array(items) => Value::Array(items.iter().map(compact_merge_request).collect()),
+    other => other,
+  }
+}
+
+/// Keeps only the listed top-level keys of an object, or of every object in
+/// an array. Other values pass through untouched, as does everything when
+/// `fields` is `None` or empty.
+pub fn project_value(value: Value, fields: Option<&[String]>) -> Value {
+  let Some(fields) = fields.filter(|fields| !fields.is_empty()) else {
+    return value;
+  };
+  match value {
+    Value::Object(mut object) => {
+      object.retain(|key, _| fields.iter().any(|field| field == key));
+      Value::Object(object)
+    }
+    Value::Array(items) => Value::Array(
+      items.into_iter().map(|item| project_value(item, Some(fields))).collect(),
+    ),
+    other => other,
+  }
+}
+
+/// Applies `project_value` to the items of a page and serializes it.
+pub fn project_page(mut page: Page, fields: Option<&[String]>) -> Value {
+  page.items = project_value(page.items, fields);
+  page.into_value()
+}
+
+/// Added and removed line counts of a unified diff. Only lines inside hunks
+/// count, so `---`/`+++` file headers and `\ No newline` markers are skipped.
+pub fn count_diff_lines(diff: &str) -> (usize, usize) {
+  let (mut added, mut removed) = (0, 0);
+  let mut in_hunk = false;
+  for line in diff.lines() {
+    if line.starts_with("@@") {
+      in_hunk = true;
+    } else if line.starts_with("diff --git ") {
+      in_hunk = false;
+    } else if in_hunk {
+      match line.chars().next() {
+        Some('+') => added += 1,
+        Some('-') => removed += 1,
+        _ => {}
+      }
+    }
+  }
+  (added, removed)
+}
+
+/// Per-file `{path, added, removed}` counts for a changes response, plus
+/// totals across all files.
+pub fn diff_stats(changes: &Value) -> Value {
+  let files: Vec<Value> = changes
+    .get("changes")
+    .and_then(Value::as_array)
+    .map(Vec::as_slice)
+    .unwrap_or_default()
+    .iter()
+    .map(|change| {
+      let path_key = if change.get("deleted_file").and_then(Value::as_bool) == Some(true) {
+        "old_path"
+      } else {
+        "new_path"
+      };
+      let path = change.get(path_key).cloned().unwrap_or(Value::Null);
+      let diff = change.get("diff").and_then(Value::as_str).unwrap_or_default();
+      let (added, removed) = count_diff_lines(diff);
+      serde_json::json!({ "path": path, "added": added, "removed": removed })
+    })
+    .collect();
+  let total = |key: &str| files.iter().filter_map(|file| file[key].as_u64()).sum::<u64>();
+
+  serde_json::json!({
+    "total_files": files.len(),
+    "total_added": total("added"),
+    "total_removed": total("removed"),
+    "files": files,
+  })
+}
+
+/// Refuses a state change that GitLab would ignore or reject, with a message
+/// that says why.
+pub fn check_state_event(merge_request: &Value, event: StateEvent) -> Result<(), McpError> {
+  let iid = merge_request.get("iid").and_then(Value::as_u64).unwrap_or_default();
+  let state = merge_request.get("state").and_then(Value::as_str).unwrap_or_default();
+  let problem = match (event, state) {
+    (_, "merged") => Some("is already merged"),
+    (StateEvent::Close, "closed") => Some("is already closed"),
+    (StateEvent::Reopen, "opened") => Some("is already open"),
+    (StateEvent::Reopen, "locked") => Some("is locked"),
+    _ => None,
+  };
+  match problem {
+    Some(problem) => Err(McpError::invalid_request(
+      format!("Merge request !{} {}; nothing to {}", iid, problem, event.as_str()),
+      None,
+    )),
+    None => Ok(()),
+  }
+}
+
+pub fn state_event_payload(event: StateEvent) -> Value {
+  serde_json::json!({ "state_event": event.as_str() })
+}
+
+/// Title prefixes GitLab treats as marking a draft, lowercased.
+const DRAFT_PREFIXES: [&str; 6] = ["draft:", "[draft]", "(draft)", "wip:", "[wip]", "(wip)"];
+
+/// `title` without any leading draft prefixes.
+fn strip_draft_prefix(title: &str) -> &str {
+  let mut rest = title.trim_start();
+  while let Some(prefix) = DRAFT_PREFIXES.iter().find(|prefix| {
+    rest.get(..prefix.len()).is_some_and(|head| head.eq_ignore_ascii_case(prefix))
+  }) {
+    rest = rest[prefix.len()..].trim_start();
+  }
+  rest
+}
+
+/// `title` with exactly one `Draft: ` prefix when `draft`, or none when not.
+pub fn draft_title(title: &str, draft: bool) -> String {
+  let bare = strip_draft_prefix(title);
+  if draft {
+    format!("Draft: {}", bare)
+  } else {
+    bare.to_string()
+  }
+}
+
+/// The title and draft flags of a merge request response.
+pub fn draft_result(merge_request: &Value) -> Value {
+  let draft = merge_request
+    .get("draft")
+    .or_else(|| merge_request.get("work_in_progress"))
+    .cloned()
+    .unwrap_or(Value::Null);
+  serde_json::json!({
+    "iid": merge_request.get("iid").cloned().unwrap_or(Value::Null),
+    "title": merge_request.get("title").cloned().unwrap_or(Value::Null),
+    "draft": draft,
+    "work_in_progress": draft,
+  })
+}
+
+/// A merge request's subscription state; `changed` is false when GitLab
+/// reported that nothing needed to change.
+pub fn subscription_result(merge_request: &Value, changed: bool) -> Value {
+  serde_json::json!({
+    "iid": merge_request.get("iid").cloned().unwrap_or(Value::Null),
+    "subscribed": merge_request.get("subscribed").cloned().unwrap_or(Value::Null),
+    "changed": changed,
+  })
+}
+
+/// Checks a GitLab duration such as `3h30m`, `1w 2d`, or `-15m` (one or more
+/// numbers each followed by `mo`, `w`, `d`, `h`, `m`, or `s`) and returns it
+/// without spaces.
+pub fn validate_duration(duration: &str, allow_negative: bool) -> Result<String, McpError> {
+  let invalid = || {
+    McpError::invalid_params(
+      format!("Invalid duration '{}'; use units mo, w, d, h, m, s (e.g. \"3h30m\")", duration),
+      None,
+    )
+  };
+  let compact: String = duration.split_whitespace().collect();
+  let rest = match compact.strip_prefix('-') {
+    Some(rest) if allow_negative => rest,
+    Some(_) => return Err(invalid()),
+    None => compact.as_str(),
+  };
+  if rest.is_empty() {
+    return Err(invalid());
+  }
+
+  let mut chars = rest.chars().peekable();
+  while chars.peek().is_some() {
+    let mut digits = 0;
+    while chars.next_if(char::is_ascii_digit).is_some() {
+      digits += 1;
+    }
+    let unit: String = std::iter::from_fn(|| chars.next_if(char::is_ascii_alphabetic)).collect();
+    if digits == 0 || !["mo", "w", "d", "h", "m", "s"].contains(&unit.as_str()) {
+      return Err(invalid());
+    }
+  }
+  Ok(compact)
+}
+
+/// Aggregates for `get_review_backlog`. `pipelines` holds each merge
+/// request's latest pipeline, in the same order, when they were looked up.
+/// A merge request is approved-and-mergeable when its `MergeState` is
+/// `Mergeable`.
+pub fn review_backlog(
+  merge_requests: &[Value],
+  pipelines: Option<&[Option<Value>]>,
+  stale_days: u64,
+  now: DateTime<Utc>,
+) -> Value {
+  let iid = |merge_request: &Value| merge_request.get("iid").cloned().unwrap_or(Value::Null);
+  let stale_before = now - chrono::Duration::days(stale_days.min(36_500) as i64);
+
+  let stale: Vec<Value> = merge_requests
+    .iter()
+    .filter(|merge_request| {
+      merge_request
+        .get("updated_at")
+        .and_then(Value::as_str)
+        .and_then(|updated_at| DateTime::parse_from_rfc3339(updated_at).ok())
+        .is_some_and(|updated_at| updated_at < stale_before)
+    })
+    .map(iid)
+    .collect();
+  let mergeable: Vec<Value> = merge_requests
+    .iter()
+    .filter(|merge_request| {
+      MergeState::from_merge_request(merge_request) == Some(MergeState::Mergeable)
+    })
+    .map(iid)
+    .collect();
+  let failing: Option<Vec<Value>> = pipelines.map(|pipelines| {
+    merge_requests
+      .iter()
+      .zip(pipelines)
+      .filter(|(_, pipeline)| {
+        pipeline.as_ref().and_then(|pipeline| pipeline.get("status")).and_then(Value::as_str) == Some("failed")
+      })
+      .map(|(merge_request, _)| iid(merge_request))
+      .collect()
+  });
+
+  serde_json::json!({
+    "total_open": merge_requests.len(),
+    "failing_pipelines": failing.as_ref().map(Vec::len),
+    "approved_and_mergeable": mergeable.len(),
+    "stale": stale.len(),
+    "stale_days": stale_days,
+    "failing_pipeline_iids": failing,
+    "approved_and_mergeable_iids": mergeable,
+    "stale_iids": stale,
+  })
+}
+
+/// Keeps the jobs whose `name` contains `needle`, ignoring case.
+pub fn filter_jobs_by_name(jobs: Value, needle: &str) -> Value {
+  let needle = needle.to_lowercase();
+  match jobs {
+    Value::Array(jobs) => Value::Array(
+      jobs
+        .into_iter()
+        .filter(|job| {
+          job["name"].as_str().is_some_and(|name| name.to_lowercase().contains(&needle))
+        })
+        .collect(),
+    ),
+    other => other,
+  }
+}
+
+/// The gist of a merge request in one small object. Each input other than
+/// the merge request itself is `None` when its fetch failed, and the fields
+/// built from it are then `null`.
+pub fn merge_request_summary(
+  merge_request: &Value,
+  stats: Option<&Value>,
+  commits: Option<&Value>,
+  discussions: Option<&Value>,
+  pipeline: Option<Option<&Value>>,
+) -> Value {
+  let count = |items: Option<&Value>| items.and_then(Value::as_array).map(Vec::len);
+  let unresolved = discussions
+    .and_then(Value::as_array)
+    .map(|discussions| discussions.iter().filter(|discussion| is_unresolved(discussion)).count());
+  let pipeline_status = match pipeline {
+    Some(Some(pipeline)) => pipeline.get("status").cloned().unwrap_or(Value::Null),
+    Some(None) => Value::String("none".to_string()),
+    None => Value::Null,
+  };
+
+  serde_json::json!({
+    "iid": merge_request["iid"],
+    "title": merge_request["title"],
+    "state": merge_request["state"],
+    "draft": merge_request["draft"],
+    "author": merge_request["author"]["username"],
+    "source_branch": merge_request["source_branch"],
+    "target_branch": merge_request["target_branch"],
+    "merge_state": MergeState::from_merge_request(merge_request),
+    "web_url": merge_request["web_url"],
+    "files_changed": stats.map(|stats| stats["total_files"].clone()),
+    "lines_added": stats.map(|stats| stats["total_added"].clone()),
+    "lines_removed": stats.map(|stats| stats["total_removed"].clone()),
+    "commit_count": count(commits),
+    "unresolved_threads": unresolved,
+    "pipeline_status": pipeline_status,
+  })
+}
+
+/// Paths of a diff list's entries, old and new, for matching renames too.
+fn diff_paths(diffs: &Value) -> Vec<&str> {
+  diffs
+    .as_array()
+    .map(Vec::as_slice)
+    .unwrap_or_default()
+    .iter()
+    .flat_map(|diff| ["old_path", "new_path"].map(|key| diff[key].as_str()))
+    .flatten()
+    .collect()
+}
+
+/// Files the merge request changes that the target branch has also changed
+/// since the merge base (`target_compare` is a compare from `base_sha` to
+/// the target branch). GitLab's API does not list conflicting files, so
+/// these are the candidates: every conflict is in one of them.
+pub fn overlapping_files(changes: &Value, target_compare: &Value) -> Vec<String> {
+  let target_paths = diff_paths(&target_compare["diffs"]);
+  let mut files: Vec<String> = diff_paths(&changes["changes"])
+    .into_iter()
+    .filter(|path| target_paths.contains(path))
+    .map(str::to_string)
+    .collect();
+  files.sort();
+  files.dedup();
+  files
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use serde_json::json;
+
+  #[test]
+  fn count_diff_lines_counts_hunk_lines_only() {
+    let diff = "@@ -1,4 +1,5 @@\n fn main() {\n-    println!(\"old\");\n+    println!(\"new\");\n+    println!(\"more\");\n }\n";
+    assert_eq!(count_diff_lines(diff), (2, 1));
+  }
+
+  #[test]
+  fn count_diff_lines_skips_file_headers_and_markers() {
+    let diff = "diff --git a/a.txt b/a.txt\n--- a/a.txt\n+++ b/a.txt\n@@ -1 +1 @@\n-a\n\\ No newline at end of file\n+b\n\\ No newline at end of file\n";
+    assert_eq!(count_diff_lines(diff), (1, 1));
+  }
+
+  #[test]
+  fn count_diff_lines_handles_several_hunks() {
+    let diff = "@@ -1,2 +1,2 @@\n-one\n+uno\n two\n@@ -10,3 +10,1 @@\n-ten\n-eleven\n twelve\n";
+    assert_eq!(count_diff_lines(diff), (1, 3));
+    assert_eq!(count_diff_lines(""), (0, 0));
+  }
+
+  #[test]
+  fn diff_stats_reports_files_and_totals() {
+    let changes = json!({
+      "changes": [
+        { "new_path": "src/lib.rs", "old_path": "src/lib.rs", "diff": "@@ -1 +1,2 @@\n-a\n+b\n+c\n" },
+        { "new_path": "old.rs", "old_path": "old.rs", "deleted_file": true, "diff": "@@ -1,2 +0,0 @@\n-x\n-y\n" },
+      ]
+    });
+    assert_eq!(
+      diff_stats(&changes),
+      json!({
+        "total_files": 2,
+        "total_added": 2,
+        "total_removed": 3,
+        "files": [
+          { "path": "src/lib.rs", "added": 2, "removed": 1 },
+          { "path": "old.rs", "added": 0, "removed": 2 },
+        ],
+      })
+    );
+  }
+
+  #[test]
+  fn project_value_keeps_requested_keys_of_objects_and_arrays() {
+    let fields = vec!["iid".to_string(), "title".to_string(), "missing".to_string()];
+    let merge_request = json!({"iid": 1, "title": "Fix", "state": "opened"});
+    assert_eq!(
+      project_value(merge_request.clone(), Some(&fields)),
+      json!({"iid": 1, "title": "Fix"})
+    );
+    assert_eq!(
+      project_value(json!([merge_request, 7]), Some(&fields)),
+      json!([{"iid": 1, "title": "Fix"}, 7])
+    );
+  }
+
+  #[test]
+  fn project_value_without_fields_returns_value_unchanged() {
+    let value = json!({"iid": 1, "state": "opened"});
+    assert_eq!(project_value(value.clone(), None), value);
+    assert_eq!(project_value(value.clone(), Some(&[])), value);
+  }
+
+  #[test]
+  fn pipeline_failures_sorts_by_suite_and_truncates_traces() {
+    let report = json!({
+      "test_suites": [
+        {
+          "name": "rspec",
+          "test_cases": [
+            { "name": "passes", "classname": "A", "status": "success" },
+            { "name": "breaks", "classname": "B", "status": "failed", "stack_trace": "abcdef" },
+          ],
+        },
+        {
+          "name": "jest",
+          "test_cases": [{ "name": "errors", "classname": "C", "status": "error", "stack_trace": null }],
+        },
+      ],
+    });
+
+    assert_eq!(
+      pipeline_failures(&report, 3),
+      json!({
+        "status": "failing",
+        "failures": [
+          { "suite": "jest", "name": "errors", "classname": "C", "stack_trace": null },
+          { "suite": "rspec", "name": "breaks", "classname": "B", "stack_trace": "abc\n... (3 more characters)" },
+        ],
+      })
+    );
+  }
+
+  #[test]
+  fn pipeline_failures_reports_passing_when_nothing_failed() {
+    let report = json!({ "test_suites": [{ "name": "rspec", "test_cases": [{ "status": "success" }] }] });
+    assert_eq!(pipeline_failures(&report, 10), json!({ "status": "passing", "failures": [] }));
+  }
+
+  #[test]
+  fn merge_request_locator_joins_namespace_and_name() {
+    let locator: MergeRequestLocator = serde_json::from_value(json!({
+      "namespace": "group/subgroup/",
+      "name": "project",
+      "merge_request_iid": 4,
+    }))
+    .unwrap();
+    assert_eq!(locator.project, "group/subgroup/project");
+    assert_eq!(locator.merge_request_iid, 4);
+
+    let both = json!({ "project": "group/project", "namespace": "group", "name": "project", "merge_request_iid": 4 });
+    assert!(serde_json::from_value::<MergeRequestLocator>(both).is_err());
+    let half = json!({ "namespace": "group", "merge_request_iid": 4 });
+    assert!(serde_json::from_value::<MergeRequestLocator>(half).is_err());
+  }
+
+  #[test]
+  fn merge_request_summary_fills_nulls_for_missing_parts() {
+    let merge_request = json!({ "iid": 5, "title": "Fix", "author": { "username": "alice" } });
+    let stats = json!({ "total_files": 2, "total_added": 10, "total_removed": 3 });
+    let discussions = json!([
+      { "notes": [{ "resolvable": true, "resolved": false }] },
+      { "notes": [{ "resolvable": true, "resolved": true }] },
+    ]);
+    let summary = merge_request_summary(&merge_request, Some(&stats), None, Some(&discussions), Some(None));
+
+    assert_eq!(summary["author"], "alice");
+    assert_eq!(summary["files_changed"], 2);
+    assert_eq!(summary["lines_added"], 10);
+    assert_eq!(summary["commit_count"], Value::Null);
+    assert_eq!(summary["unresolved_threads"], 1);
+    assert_eq!(summary["pipeline_status"], "none");
+  }
+
+  #[test]
+  fn apply_severity_prefixes_body_from_config_or_defaults() {
+    let review = ReviewConfig {
+      severity_prefixes: [("blocker".to_string(), "🚫 **blocker:**".to_string())].into(),
+    };
+    let mut body = "Null check missing".to_string();
+    apply_severity(&mut body, Some("Issue"), &review).unwrap();
+    assert_eq!(body, "**issue:** Null check missing");
+
+    let mut body = "Breaks the build".to_string();
+    apply_severity(&mut body, Some("blocker"), &review).unwrap();
+    assert_eq!(body, "🚫 **blocker:** Breaks the build");
+
+    let mut body = "Unchanged".to_string();
+    apply_severity(&mut body, None, &review).unwrap();
+    assert_eq!(body, "Unchanged");
+    assert!(apply_severity(&mut body, Some("urgent"), &review).is_err());
+  }
+
+  #[test]
+  fn overlapping_files_lists_paths_changed_on_both_sides() {
+    let changes = json!({ "changes": [
+      { "old_path": "src/a.rs", "new_path": "src/a.rs" },
+      { "old_path": "src/old.rs", "new_path": "src/new.rs" },
+      { "old_path": "README.md", "new_path": "README.md" },
+    ] });
+    let target_compare = json!({ "diffs": [
+      { "old_path": "src/old.rs", "new_path": "src/old.rs" },
+      { "old_path": "src/a.rs", "new_path": "src/a.rs" },
+      { "old_path": "Cargo.toml", "new_path": "Cargo.toml" },
+    ] });
+    assert_eq!(overlapping_files(&changes, &target_compare), vec!["src/a.rs", "src/old.rs"]);
+  }
+
+  #[test]
+  fn truncate_result_caps_text_and_appends_marker() {
+    let result = text_result_chunked(&"é".repeat(10), 8);
+    assert_eq!(result_size(&result), 20);
+
+    let truncated = truncate_result(result, 7);
+    let texts: Vec<&str> = truncated
+      .content
+      .iter()
+      .filter_map(|content| content.as_text())
+      .map(|text| text.text.as_str())
+      .collect();
+    assert_eq!(texts.len(), 2);
+    assert_eq!(texts[0], "ééé");
+    assert_eq!(
+      serde_json::from_str::<Value>(texts[1]).unwrap(),
+      json!({ "truncated": true, "original_size": 20, "max_response_bytes": 7 })
+    );
+  }
+
+  #[test]
+  fn merge_state_prefers_detailed_status_and_maps_legacy_values() {
+    let detailed = json!({"detailed_merge_status": "ci_still_running", "merge_status": "can_be_merged"});
+    assert_eq!(MergeState::from_merge_request(&detailed), Some(MergeState::CiStillRunning));
+
+    let legacy = json!({"merge_status": "can_be_merged"});
+    assert_eq!(MergeState::from_merge_request(&legacy), Some(MergeState::Mergeable));
+    assert_eq!(MergeState::from_merge_request(&json!({"merge_status": "cannot_be_merged"})), Some(MergeState::Conflict));
+    assert_eq!(MergeState::from_merge_request(&json!({})), None);
+
+    let future = MergeState::parse("security_policy_violations");
+    assert_eq!(future, MergeState::Unknown("security_policy_violations".to_string()));
+    assert_eq!(serde_json::to_value(&future).unwrap(), json!("security_policy_violations"));
+
+    let merge_request = MergeRequest::from_value(json!({"iid": 1, "merge_status": "can_be_merged"})).unwrap();
+    assert_eq!(merge_request.into_value()["merge_state"], json!("mergeable"));
+  }
+
+  #[test]
+  fn merge_request_locator_rejects_zero_iid_and_empty_project() {
+    let zero_iid = MergeRequestLocator { project: "group/project".to_string(), merge_request_iid: 0 };
+    assert!(zero_iid.validate().unwrap_err().message.contains("merge_request_iid"));
+    let empty_project = MergeRequestLocator { project: " ".to_string(), merge_request_iid: 1 };
+    assert!(empty_project.validate().unwrap_err().message.contains("project"));
+
+    let parsed = serde_json::from_value::<MergeRequestLocator>(json!({ "project": "group/project", "merge_request_iid": 0 }));
+    assert!(parsed.unwrap_err().to_string().contains("merge_request_iid must be greater than 0"));
+  }
+
+  #[test]
+  fn diff_lines_numbers_context_added_and_removed_lines() {
+    let diff = "--- a/src/lib.rs\n+++ b/src/lib.rs\n@@ -10,3 +10,3 @@ fn main() {\n keep\n-old\n+new\n\\ No newline at end of file\n@@ -40 +40,2 @@\n+added\n tail\n";
+    let numbered: Vec<(Option<u64>, Option<u64>, &str)> = diff_lines(diff)
+      .into_iter()
+      .map(|line| (line.old_line, line.new_line, line.text))
+      .collect();
+    assert_eq!(numbered, vec![
+      (Some(10), Some(10), " keep"),
+      (Some(11), None, "-old"),
+      (None, Some(11), "+new"),
+      (None, Some(40), "+added"),
+      (Some(40), Some(41), " tail"),
+    ]);
+  }
+
+  #[test]
+  fn validate_position_in_changes_checks_lines_against_the_diff() {
+    let changes = json!({ "changes": [
+      { "old_path": "src/old.rs", "new_path": "src/new.rs", "diff": "@@ -1,2 +1,2 @@\n same\n-before\n+after\n" },
+      { "old_path": "big.json", "new_path": "big.json", "diff": "", "too_large": true },
+    ] });
+    let position = |value: Value| validate_position_in_changes(&value, &changes);
+
+    assert!(position(json!({ "new_path": "src/new.rs", "old_path": "src/old.rs", "new_line": 2 })).is_ok());
+    assert!(position(json!({ "new_path": "src/new.rs", "old_path": "src/old.rs", "old_line": 2 })).is_ok());
+    assert!(position(json!({ "new_path": "src/new.rs", "old_path": "src/old.rs", "old_line": 1, "new_line": 1 })).is_ok());
+    // Renamed file, found by its old path alone.
+    assert!(position(json!({ "old_path": "src/old.rs", "old_line": 2 })).is_ok());
+
+    let outside = position(json!({ "new_path": "src/new.rs", "old_path": "src/old.rs", "new_line": 9 })).unwrap_err();
+    assert!(outside.message.contains("line 9 of src/new.rs"));
+    assert!(position(json!({ "new_path": "README.md", "old_path": "README.md", "new_line": 1 })).is_err());
+    // Empty diffs (too large, collapsed, or truncated) cannot be checked.
+    assert!(position(json!({ "new_path": "big.json", "old_path": "big.json", "new_line": 500 })).is_ok());
+  }
+
+  #[test]
+  fn parse_gitlab_url_reads_project_and_iid() {
+    let parse = |url: &str| parse_gitlab_url(url, None).unwrap();
+    assert_eq!(parse("https://gitlab.com/group/project"), Some(("group/project".to_string(), None)));
+    assert_eq!(
+      parse("https://gitlab.com/group/sub/project/-/merge_requests/42/diffs"),
+      Some(("group/sub/project".to_string(), Some(42)))
+    );
+    assert_eq!(parse("http://host/group/project/merge_requests/7"), Some(("group/project".to_string(), Some(7))));
+    assert_eq!(
+      parse("https://host/group/project/-/merge_requests/9?tab=notes#note_1"),
+      Some(("group/project".to_string(), Some(9)))
+    );
+    assert_eq!(parse("group/project"), None);
+    assert_eq!(parse("https://host/"), None);
+  }
+
+  #[test]
+  fn parse_gitlab_url_strips_the_instance_subpath() {
+    let instance = Some("https://host/gitlab/api/v4");
+    assert_eq!(
+      parse_gitlab_url("https://host/gitlab/group/proj/-/merge_requests/4", instance),
+      Ok(Some(("group/proj".to_string(), Some(4))))
+    );
+    // A path that only starts with the same letters keeps its path.
+    assert_eq!(
+      parse_gitlab_url("https://host/gitlabbers/proj/-/merge_requests/4", instance),
+      Ok(Some(("gitlabbers/proj".to_string(), Some(4))))
+    );
+    assert_eq!(
+      parse_gitlab_url("https://GitLab.com/group/proj", Some("https://gitlab.com/api/v4")),
+      Ok(Some(("group/proj".to_string(), None)))
+    );
+  }
+
+  #[test]
+  fn parse_gitlab_url_rejects_urls_from_another_host() {
+    let err = parse_gitlab_url(
+      "https://gitlab.com/group/proj/-/merge_requests/4",
+      Some("https://gitlab.example.com/api/v4"),
+    )
+    .unwrap_err();
+    assert_eq!(
+      err,
+      "https://gitlab.com/group/proj/-/merge_requests/4 is on gitlab.com, but this server is connected to \
+gitlab.example.com; pass the project path or ID instead"
+    );
+    assert!(parse_gitlab_url("https://other/gitlab/group/proj", Some("https://host/gitlab/api/v4")).is_err());
+  }
 }
@@ -0,0 +1,497 @@
+use std::collections::HashMap;
+
+use gitlab_mcp::gitlab::{GitLabClient, PageWalk, ProjectFilter, DEFAULT_USER_AGENT};
+use rmcp::model::ErrorCode;
+use serde_json::json;
+use wiremock::matchers::{body_json, header, method, path, query_param};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+const TOKEN: &str = "test-token";
+
+async fn client(server: &MockServer) -> GitLabClient {
+    GitLabClient::from_api_url(server.uri(), TOKEN.to_string()).expect("client should build")
+}
+
+#[tokio::test]
+async fn get_merge_request_sends_token_to_encoded_project_path() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/projects/group%2Fproject/merge_requests/7"))
+        .and(header("PRIVATE-TOKEN", TOKEN))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "iid": 7, "title": "Fix" })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let merge_request = client(&server)
+        .await
+        .get_merge_request("group/project", 7)
+        .await
+        .expect("request should succeed");
+
+    assert_eq!(merge_request["iid"], 7);
+    assert_eq!(merge_request["title"], "Fix");
+}
+
+#[tokio::test]
+async fn create_merge_request_discussion_posts_payload() {
+    let server = MockServer::start().await;
+    let payload = json!({ "body": "Looks good" });
+    Mock::given(method("POST"))
+        .and(path("/projects/42/merge_requests/3/discussions"))
+        .and(header("PRIVATE-TOKEN", TOKEN))
+        .and(body_json(&payload))
+        .respond_with(ResponseTemplate::new(201).set_body_json(json!({ "id": "abc", "notes": [] })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let discussion = client(&server)
+        .await
+        .create_merge_request_discussion("42", 3, payload)
+        .await
+        .expect("request should succeed");
+
+    assert_eq!(discussion["id"], "abc");
+}
+
+#[tokio::test]
+async fn new_appends_api_v4_to_instance_url() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/api/v4/projects/42/merge_requests/1"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "iid": 1 })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let client = GitLabClient::new(server.uri(), TOKEN.to_string()).expect("client should build");
+    let merge_request = client.get_merge_request("42", 1).await.expect("request should succeed");
+
+    assert_eq!(merge_request["iid"], 1);
+}
+
+#[tokio::test]
+async fn url_credentials_are_sent_as_oauth_token_not_in_url() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/api/v4/projects/42/merge_requests/1"))
+        .and(header("Authorization", "Bearer glpat-xxx"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "iid": 1 })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let url = server.uri().replacen("://", "://oauth2:glpat-xxx@", 1);
+    let client = GitLabClient::new(url, String::new()).expect("client should build");
+    assert!(!client.base_url().contains("glpat-xxx"));
+    let merge_request = client.get_merge_request("42", 1).await.expect("request should succeed");
+
+    assert_eq!(merge_request["iid"], 1);
+}
+
+#[tokio::test]
+async fn user_agent_defaults_to_crate_version_and_can_be_overridden() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/projects/42/merge_requests/1"))
+        .and(header("User-Agent", DEFAULT_USER_AGENT))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "iid": 1 })))
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/projects/42/merge_requests/2"))
+        .and(header("User-Agent", "review-bot/2.0"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "iid": 2 })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    assert_eq!(DEFAULT_USER_AGENT, concat!("gitlab-mcp/", env!("CARGO_PKG_VERSION")));
+    client(&server).await.get_merge_request("42", 1).await.expect("request should succeed");
+    let headers = HashMap::from([("X-Gateway-Org".to_string(), "acme".to_string())]);
+    client(&server)
+        .await
+        .with_user_agent("review-bot/2.0")
+        .and_then(|client| client.with_extra_headers(&headers))
+        .expect("client should build")
+        .get_merge_request("42", 2)
+        .await
+        .expect("request should succeed");
+}
+
+#[tokio::test]
+async fn gzip_responses_are_decompressed_transparently() {
+    // `{"iid":1,"title":"Fix"}`, gzip-compressed.
+    const GZIPPED: [u8; 43] = [
+        0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0xab, 0x56, 0xca, 0xcc, 0x4c,
+        0x51, 0xb2, 0x32, 0xd4, 0x51, 0x2a, 0xc9, 0x2c, 0xc9, 0x49, 0x55, 0xb2, 0x52, 0x72, 0xcb,
+        0xac, 0x50, 0xaa, 0x05, 0x00, 0xd2, 0xbb, 0x0a, 0x26, 0x17, 0x00, 0x00, 0x00,
+    ];
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/projects/42/merge_requests/1"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("Content-Encoding", "gzip")
+                .insert_header("Content-Type", "application/json")
+                .set_body_bytes(GZIPPED.to_vec()),
+        )
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let merge_request = client(&server)
+        .await
+        .get_merge_request("42", 1)
+        .await
+        .expect("request should succeed");
+
+    assert_eq!(merge_request, json!({ "iid": 1, "title": "Fix" }));
+}
+
+#[tokio::test]
+async fn list_project_jobs_repeats_scope_parameter() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/projects/42/jobs"))
+        .and(query_param("scope[]", "failed"))
+        .and(query_param("scope[]", "canceled"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!([{ "id": 9, "name": "rspec" }])))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let page = client(&server)
+        .await
+        .list_project_jobs("42", &["failed", "canceled"], None, None)
+        .await
+        .expect("request should succeed");
+
+    assert_eq!(page.items, json!([{ "id": 9, "name": "rspec" }]));
+}
+
+#[tokio::test]
+async fn review_requests_span_projects_but_respect_the_project_filter() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/merge_requests"))
+        .and(query_param("reviewer_username", "alice"))
+        .and(query_param("state", "opened"))
+        .and(query_param("scope", "all"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!([
+            { "iid": 1, "project_id": 10, "references": { "full": "team/app!1" } },
+            { "iid": 2, "project_id": 20, "references": { "full": "secret/vault!2" } },
+        ])))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let page = client(&server)
+        .await
+        .with_project_filter(ProjectFilter::new(Vec::new(), vec!["secret/*".to_string()]))
+        .list_review_requests("alice", None, None)
+        .await
+        .expect("request should succeed");
+
+    assert_eq!(page.items, json!([{ "iid": 1, "project_id": 10, "references": { "full": "team/app!1" } }]));
+}
+
+#[tokio::test]
+async fn project_filter_resolves_numeric_ids_to_paths() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/projects/1234"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "id": 1234, "path_with_namespace": "secret/vault" })))
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/projects/1234/merge_requests/1"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "iid": 1 })))
+        .expect(0)
+        .mount(&server)
+        .await;
+
+    let client = client(&server)
+        .await
+        .with_project_filter(ProjectFilter::new(Vec::new(), vec!["secret/*".to_string()]));
+    for _ in 0..2 {
+        let err = client.get_merge_request("1234", 1).await.expect_err("denied by path");
+        assert_eq!(err.code, ErrorCode::INVALID_REQUEST);
+    }
+    let err = client.global_search("blobs", "password", None, None).await.expect_err("global search refused");
+    assert_eq!(err.code, ErrorCode::INVALID_REQUEST);
+}
+
+#[tokio::test]
+async fn mark_todo_done_posts_to_top_level_todos() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/todos/7/mark_as_done"))
+        .and(header("PRIVATE-TOKEN", TOKEN))
+        .respond_with(ResponseTemplate::new(201).set_body_json(json!({ "id": 7, "state": "done" })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let todo = client(&server).await.mark_todo_done(7).await.expect("request should succeed");
+
+    assert_eq!(todo, json!({ "id": 7, "state": "done" }));
+}
+
+#[tokio::test]
+async fn paginated_walks_move_off_host_next_links_onto_the_api_url() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/projects/1/merge_requests/2/discussions"))
+        .and(query_param("page", "2"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!([{ "id": "b" }])))
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/projects/1/merge_requests/2/discussions"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("Link", "<http://gitlab.internal/api/v4/projects/1/merge_requests/2/discussions?page=2>; rel=\"next\"")
+                .set_body_json(json!([{ "id": "a" }])),
+        )
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let page = client(&server)
+        .await
+        .list_merge_request_discussions("1", 2)
+        .await
+        .expect("off-host next link should be followed on the API host");
+
+    assert_eq!(page.items, json!([{ "id": "a" }, { "id": "b" }]));
+}
+
+#[tokio::test]
+async fn paginated_walks_stop_at_next_links_outside_the_api() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/projects/1/merge_requests/2/discussions"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("X-Next-Page", "2")
+                .insert_header("Link", "<https://evil.example/collect?page=2>; rel=\"next\"")
+                .set_body_json(json!([{ "id": "a" }])),
+        )
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let page = client(&server)
+        .await
+        .list_merge_request_discussions("1", 2)
+        .await
+        .expect("the pages collected so far should be returned");
+
+    assert_eq!(page.items, json!([{ "id": "a" }]));
+    assert_eq!(page.next_page, Some(2));
+}
+
+#[tokio::test]
+async fn keyset_walks_follow_link_cursors() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/projects/1/merge_requests"))
+        .and(query_param("cursor", "abc"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!([{ "iid": 1 }])))
+        .expect(1)
+        .mount(&server)
+        .await;
+    let next = format!(
+        "<{}/projects/1/merge_requests?cursor=abc&order_by=id&pagination=keyset&per_page=1>; rel=\"next\"",
+        server.uri()
+    );
+    Mock::given(method("GET"))
+        .and(path("/projects/1/merge_requests"))
+        .and(query_param("pagination", "keyset"))
+        .and(query_param("order_by", "id"))
+        .respond_with(ResponseTemplate::new(200).insert_header("Link", next.as_str()).set_body_json(json!([{ "iid": 2 }])))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let walk = PageWalk { keyset: true, max_items: 10 };
+    let page = client(&server)
+        .await
+        .list_merge_requests("1", &[("order_by", "id".to_string()), ("per_page", "1".to_string())], Some(walk))
+        .await
+        .expect("keyset walk should succeed");
+
+    assert_eq!(page.items, json!([{ "iid": 2 }, { "iid": 1 }]));
+    assert_eq!(page.next_page, None);
+}
+
+#[tokio::test]
+async fn mark_todo_done_refuses_todos_from_denied_projects() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/todos"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!([
+            { "id": 7, "project": { "id": 20, "path_with_namespace": "secret/vault" } },
+        ])))
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/todos/7/mark_as_done"))
+        .respond_with(ResponseTemplate::new(201).set_body_json(json!({ "id": 7 })))
+        .expect(0)
+        .mount(&server)
+        .await;
+
+    let err = client(&server)
+        .await
+        .with_project_filter(ProjectFilter::new(Vec::new(), vec!["secret/*".to_string()]))
+        .mark_todo_done(7)
+        .await
+        .expect_err("denied project");
+
+    assert_eq!(err.code, ErrorCode::INVALID_REQUEST);
+}
+
+#[tokio::test]
+async fn list_todos_returns_a_page_without_denied_projects() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/todos"))
+        .and(query_param("page", "1"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("X-Next-Page", "2")
+                .insert_header("X-Total", "45")
+                .set_body_json(json!([
+                    { "id": 7, "project": { "id": 20, "path_with_namespace": "secret/vault" } },
+                    { "id": 8, "project": { "id": 21, "path_with_namespace": "group/app" } },
+                ])),
+        )
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let page = client(&server)
+        .await
+        .with_project_filter(ProjectFilter::new(Vec::new(), vec!["secret/*".to_string()]))
+        .list_todos(&[("page", "1".to_string())])
+        .await
+        .expect("list_todos should succeed");
+
+    assert_eq!(page.items, json!([{ "id": 8, "project": { "id": 21, "path_with_namespace": "group/app" } }]));
+    assert_eq!(page.next_page, Some(2));
+    assert_eq!(page.total, Some(45));
+}
+
+#[tokio::test]
+async fn extra_headers_are_sent_with_every_request() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/projects/42/merge_requests/1"))
+        .and(header("X-Gateway-Org", "acme"))
+        .and(header("PRIVATE-TOKEN", TOKEN))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "iid": 1 })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let headers = HashMap::from([("X-Gateway-Org".to_string(), "acme".to_string())]);
+    let client = client(&server)
+        .await
+        .with_extra_headers(&headers)
+        .expect("headers should be accepted");
+
+    client.get_merge_request("42", 1).await.expect("request should succeed");
+}
+
+#[tokio::test]
+async fn extra_headers_cannot_replace_the_token() {
+    let server = MockServer::start().await;
+    for name in ["PRIVATE-TOKEN", "private-token", "Authorization"] {
+        let headers = HashMap::from([(name.to_string(), "other".to_string())]);
+        assert!(client(&server).await.with_extra_headers(&headers).is_err(), "{}", name);
+    }
+}
+
+#[tokio::test]
+async fn plain_text_bodies_are_returned_verbatim() {
+    let server = MockServer::start().await;
+    let log = "Running with gitlab-runner 17.0\n$ cargo test\ntest result: ok\n";
+    Mock::given(method("GET"))
+        .and(path("/projects/42/jobs/9/trace"))
+        .respond_with(ResponseTemplate::new(200).set_body_raw(log, "text/plain"))
+        .mount(&server)
+        .await;
+
+    let trace = client(&server)
+        .await
+        .get_job_trace("42", 9)
+        .await
+        .expect("request should succeed");
+
+    assert_eq!(trace, log);
+}
+
+#[tokio::test]
+async fn subscribe_when_already_subscribed_is_a_no_op() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/projects/42/merge_requests/5/subscribe"))
+        .respond_with(ResponseTemplate::new(304))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let updated = client(&server)
+        .await
+        .subscribe_merge_request("42", 5)
+        .await
+        .expect("304 should not be an error");
+
+    assert_eq!(updated, None);
+}
+
+async fn error_for_status(status: u16, body: serde_json::Value) -> rmcp::model::ErrorData {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/projects/42/merge_requests/1"))
+        .respond_with(ResponseTemplate::new(status).set_body_json(body))
+        .mount(&server)
+        .await;
+
+    client(&server)
+        .await
+        .get_merge_request("42", 1)
+        .await
+        .expect_err("request should fail")
+}
+
+#[tokio::test]
+async fn not_found_maps_to_invalid_params() {
+    let err = error_for_status(404, json!({ "message": "404 Not found" })).await;
+
+    assert_eq!(err.code, ErrorCode::INVALID_PARAMS);
+    assert_eq!(err.message, "GitLab resource not found");
+    assert_eq!(err.data, Some(json!({ "message": "404 Not found" })));
+}
+
+#[tokio::test]
+async fn unprocessable_entity_maps_to_validation_error() {
+    let err = error_for_status(422, json!({ "message": { "base": ["Tag already exists"] } })).await;
+
+    assert_eq!(err.code, ErrorCode::INVALID_PARAMS);
+    assert_eq!(err.message, "GitLab reported a validation error");
+}
+
+#[tokio::test]
+async fn too_many_requests_maps_to_rate_limit_error() {
+    let err = error_for_status(429, json!({ "message": "Retry later" })).await;
+
+    assert_eq!(err.code, ErrorCode::INTERNAL_ERROR);
+    assert_eq!(err.message, "GitLab rate limit exceeded; retry later");
+}
@@ -7,12 +7,17 @@ async fn test_server_creation() {
         server: gitlab_mcp::config::ServerConfig {
             name: "test-server".to_string(),
             transport: gitlab_mcp::config::TransportType::Stdio,
+            ..Default::default()
         },
         telemetry: gitlab_mcp::config::TelemetryConfig {
             level: "error".to_string(),
             format: gitlab_mcp::config::LogFormat::Pretty,
             file: None,
+            ..Default::default()
         },
+        audit: Default::default(),
+        gitlab: Default::default(),
+        review: Default::default(),
     };
 
     // Test server creation - this should work without any complex setup
@@ -31,12 +36,17 @@ async fn test_config_validation() {
         server: gitlab_mcp::config::ServerConfig {
             name: "test-config-server".to_string(),
             transport: gitlab_mcp::config::TransportType::Stdio,
+            ..Default::default()
         },
         telemetry: gitlab_mcp::config::TelemetryConfig {
             level: "debug".to_string(),
             format: gitlab_mcp::config::LogFormat::Json,
             file: Some("/tmp/test.log".to_string()),
+            ..Default::default()
         },
+        audit: Default::default(),
+        gitlab: Default::default(),
+        review: Default::default(),
     };
 
     let server = Server::new(config).await;
@@ -45,3 +55,190 @@ async fn test_config_validation() {
         "Server should handle different config options"
     );
 }
+
+/// Sends `messages` to `server` as newline-delimited JSON-RPC over an
+/// in-memory pipe and returns the response to each request, by id.
+async fn exchange(server: Server, messages: &[serde_json::Value]) -> Vec<serde_json::Value> {
+    use rmcp::ServiceExt;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+    let (client, transport) = tokio::io::duplex(1 << 20);
+    let running = tokio::spawn(async move {
+        let service = server.serve(tokio::io::split(transport)).await.expect("server should start");
+        let _ = service.waiting().await;
+    });
+    let (read, mut write) = tokio::io::split(client);
+    let mut lines = BufReader::new(read).lines();
+    let mut responses = Vec::new();
+    for message in messages {
+        write.write_all(format!("{}\n", message).as_bytes()).await.unwrap();
+        if message.get("id").is_none() {
+            continue;
+        }
+        loop {
+            let line = lines.next_line().await.unwrap().expect("server closed the pipe");
+            let response: serde_json::Value = serde_json::from_str(&line).unwrap();
+            if response.get("id") == message.get("id") {
+                responses.push(response);
+                break;
+            }
+        }
+    }
+    drop(write);
+    running.abort();
+    responses
+}
+
+/// `Server::new` reads `GITLAB_URL` from the environment, so tests that point
+/// it at their own mock server take turns.
+static GITLAB_ENV: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+/// A server talking to `gitlab`, reading diffs from `/changes`.
+async fn mock_server(gitlab: &wiremock::MockServer, server: gitlab_mcp::config::ServerConfig) -> Server {
+    std::env::set_var("GITLAB_URL", gitlab.uri());
+    std::env::set_var("GITLAB_TOKEN", "test-token");
+    let config = Config {
+        server: gitlab_mcp::config::ServerConfig {
+            name: "test-server".to_string(),
+            transport: gitlab_mcp::config::TransportType::Stdio,
+            ..server
+        },
+        telemetry: gitlab_mcp::config::TelemetryConfig {
+            level: "error".to_string(),
+            ..Default::default()
+        },
+        audit: Default::default(),
+        gitlab: gitlab_mcp::config::GitLabConfig {
+            diffs_endpoint: gitlab_mcp::config::DiffsEndpoint::Changes,
+            ..Default::default()
+        },
+        review: Default::default(),
+    };
+    Server::new(config).await.expect("server should build")
+}
+
+fn initialize() -> serde_json::Value {
+    serde_json::json!({
+        "jsonrpc": "2.0",
+        "id": 0,
+        "method": "initialize",
+        "params": {
+            "protocolVersion": "2025-03-26",
+            "capabilities": {},
+            "clientInfo": { "name": "test", "version": "0" }
+        }
+    })
+}
+
+fn tool_call(id: u64, name: &str, arguments: serde_json::Value) -> serde_json::Value {
+    serde_json::json!({ "jsonrpc": "2.0", "id": id, "method": "tools/call", "params": { "name": name, "arguments": arguments } })
+}
+
+/// Answers `GET /projects/group%2Fproject` with project 42.
+async fn mount_project(gitlab: &wiremock::MockServer) {
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, ResponseTemplate};
+
+    Mock::given(method("GET"))
+        .and(path("/api/v4/projects/group%2Fproject"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(serde_json::json!({ "id": 42, "path_with_namespace": "group/project" })),
+        )
+        .mount(gitlab)
+        .await;
+}
+
+#[tokio::test]
+async fn oversized_write_results_still_invalidate_the_cache() {
+    use serde_json::json;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let _env = GITLAB_ENV.lock().await;
+    let gitlab = MockServer::start().await;
+    mount_project(&gitlab).await;
+    Mock::given(method("GET"))
+        .and(path("/api/v4/projects/group%2Fproject/merge_requests/1/changes"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "changes": [] })))
+        .expect(2)
+        .mount(&gitlab)
+        .await;
+    Mock::given(method("PUT"))
+        .and(path("/api/v4/projects/group%2Fproject/merge_requests/1/merge"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(json!({ "iid": 1, "state": "merged", "description": "x".repeat(4096) })),
+        )
+        .expect(1)
+        .mount(&gitlab)
+        .await;
+    let server = mock_server(
+        &gitlab,
+        gitlab_mcp::config::ServerConfig {
+            max_response_bytes: Some(1024),
+            oversize_response: gitlab_mcp::config::OversizeResponse::Error,
+            ..Default::default()
+        },
+    )
+    .await;
+
+    let locator = json!({ "project": "group/project", "merge_request_iid": 1 });
+    let responses = exchange(
+        server,
+        &[
+            initialize(),
+            json!({ "jsonrpc": "2.0", "method": "notifications/initialized" }),
+            tool_call(1, "get_merge_request_changes", locator.clone()),
+            tool_call(2, "merge_merge_request", locator.clone()),
+            tool_call(3, "get_merge_request_changes", locator),
+        ],
+    )
+    .await;
+
+    assert!(responses[1].get("result").is_some(), "{}", responses[1]);
+    let error = &responses[2]["error"];
+    assert!(error["message"].as_str().unwrap().contains("byte limit"), "{}", responses[2]);
+    assert!(responses[3].get("result").is_some(), "{}", responses[3]);
+}
+
+#[tokio::test]
+async fn writes_invalidate_the_cache_whichever_way_the_project_is_named() {
+    use serde_json::json;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let _env = GITLAB_ENV.lock().await;
+    let gitlab = MockServer::start().await;
+    mount_project(&gitlab).await;
+    Mock::given(method("GET"))
+        .and(path("/api/v4/projects/group%2Fproject/merge_requests/1/changes"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "changes": [] })))
+        .expect(2)
+        .mount(&gitlab)
+        .await;
+    Mock::given(method("PUT"))
+        .and(path("/api/v4/projects/42/merge_requests/1/merge"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "iid": 1, "state": "merged" })))
+        .expect(1)
+        .mount(&gitlab)
+        .await;
+    let server = mock_server(&gitlab, Default::default()).await;
+
+    let by_path = json!({ "project": "group/project", "merge_request_iid": 1 });
+    let responses = exchange(
+        server,
+        &[
+            initialize(),
+            json!({ "jsonrpc": "2.0", "method": "notifications/initialized" }),
+            tool_call(1, "get_merge_request_changes", by_path.clone()),
+            tool_call(2, "get_merge_request_changes", by_path.clone()),
+            tool_call(3, "merge_merge_request", json!({ "project": "42", "merge_request_iid": 1 })),
+            tool_call(4, "get_merge_request_changes", by_path),
+        ],
+    )
+    .await;
+
+    for response in &responses[1..] {
+        assert!(response.get("result").is_some(), "{}", response);
+    }
+}